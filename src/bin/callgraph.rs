@@ -2,32 +2,93 @@
 
 extern crate rustc_driver;
 extern crate rustc_interface;
+extern crate rustc_middle;
+extern crate rustc_session;
+extern crate rustc_span;
 
 use rustc_driver::{Callbacks, Compilation};
 use rustc_interface::Queries;
 use rustc_interface::interface::Compiler;
+use rustc_middle::ty::TyCtxt;
+use rustc_session::config::CrateType;
+use rustc_span::def_id::LOCAL_CRATE;
 
-use callgraph::{analyze, compile_time_sysroot};
+use callgraph::{analyze_with, compile_time_sysroot, emit, save, Backend, Config};
 
-struct CallgraphCallbacks;
+struct CallgraphCallbacks {
+    config: Config,
+}
 
 impl Callbacks for CallgraphCallbacks {
+    fn config(&mut self, config: &mut rustc_interface::Config) {
+        // the MIR inliner would remove the calls to small functions from `optimized_mir`
+        // under `-O`, nothing is generated anyway
+        if self.config.backend == Backend::Mir {
+            config.opts.unstable_opts.inline_mir = Some(false);
+        }
+    }
 
     fn after_analysis<'tcx>(&mut self, _compiler: &Compiler, queries: &'tcx Queries<'tcx>) -> Compilation {
         // let expanded_crate = &compiler.expansion().unwrap().peek().0;
         queries.global_ctxt().unwrap().enter(|tcx| {
-            analyze(&tcx);
+            let mut graph = analyze_with(tcx, &self.config);
+            if let Some(target) = cargo_target(tcx) {
+                graph.set_target(&target);
+            }
+            match &self.config.save_dir {
+                Some(dir) => {
+                    save(tcx, &graph, dir).expect("failed to save the call graph");
+                }
+                None => emit(&graph, &self.config),
+            }
         });
 
         Compilation::Stop
     }
 }
 
+/// Returns the Cargo target of the crate being compiled, like `test:parsing`.
+///
+/// `cargo-callgraph` sets `CALLGRAPH_TARGET` to the target it checks, but other local crates
+/// get built along the way. Those are always libraries the target depends on.
+fn cargo_target(tcx: TyCtxt<'_>) -> Option<String> {
+    let target = std::env::var("CALLGRAPH_TARGET").ok()?;
+    let (kind, name) = target.split_once(':')?;
+    let crate_name = tcx.crate_name(LOCAL_CRATE);
+
+    let is_harness = matches!(kind, "test" | "bench");
+    let is_lib = !tcx.crate_types().contains(&CrateType::Executable);
+    if name.replace('-', "_") == crate_name.as_str()
+        && is_harness == tcx.sess.opts.test
+        && (kind == "lib") == is_lib
+    {
+        Some(target)
+    } else {
+        Some(format!("lib:{}", crate_name))
+    }
+}
+
 //作为一个编译器插件运行
 //在编译过程中收集代码的调用关系信息
 fn main() {
     let mut args: Vec<_> = std::env::args().collect();
 
+    // `callgraph query ...`, `callgraph diff ...` and `callgraph link ...` work on saved
+    // graphs, without running the compiler
+    let subcommand = match args.get(1).map(String::as_str) {
+        Some("query") => Some(callgraph::query::run as fn(Vec<String>) -> Result<(), String>),
+        Some("diff") => Some(callgraph::diff::run as fn(Vec<String>) -> Result<(), String>),
+        Some("link") => Some(callgraph::link::run as fn(Vec<String>) -> Result<(), String>),
+        _ => None,
+    };
+    if let Some(run) = subcommand {
+        if let Err(e) = run(args.split_off(2)) {
+            eprintln!("error: {}", e);
+            std::process::exit(1)
+        }
+        return;
+    }
+
     // Make sure we use the right default sysroot. The default sysroot is wrong,
     // because `get_or_default_sysroot` in `librustc_session` bases that on `current_exe`.
     //
@@ -43,7 +104,13 @@ fn main() {
         }
     }
 
-    let mut calls = CallgraphCallbacks;
+    // Strip our own flags (e.g. `--format json`) before handing the rest to rustc.
+    let config = Config::from_args(&mut args).unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1)
+    });
+
+    let mut calls = CallgraphCallbacks { config };
 
     let run_compiler = rustc_driver::RunCompiler::new(&args, &mut calls);
     run_compiler.run().expect("Compilation failed");
@@ -1,7 +1,8 @@
 
-///! This implementation is based on `cargo-miri`
-///! https://github.com/rust-lang/miri/blob/master/src/bin/cargo-miri.rs
+//! This implementation is based on `cargo-miri`
+//! https://github.com/rust-lang/miri/blob/master/src/bin/cargo-miri.rs
 
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fmt::Display;
 use std::path::{Path, PathBuf};
@@ -10,6 +11,9 @@ use std::time::Duration;
 
 use rustc_version::VersionMeta;
 
+use callgraph::link::link_saved;
+use callgraph::{emit, Config};
+
 use wait_timeout::ChildExt;
 
 //显示错误信息并退出
@@ -42,10 +46,9 @@ fn get_arg_flag_value(name: &str) -> Option<String> {
         if suffix.is_empty() {
             // This argument is exactly `name`; the next one is the value.
             return args.next();
-        } else if suffix.starts_with('=') {
+        } else if let Some(value) = suffix.strip_prefix('=') {
             // This argument is `name=value`; get the value.
-            // Strip leading `=`.
-            return Some(suffix[1..].to_owned());
+            return Some(value.to_owned());
         }
     }
 }
@@ -74,10 +77,9 @@ where
                 Some(arg) => arg,
                 None => return false,
             }
-        } else if suffix.starts_with('=') {
+        } else if let Some(value) = suffix.strip_prefix('=') {
             // This argument is `name=value`; get the value.
-            // Strip leading `=`.
-            suffix[1..].to_owned()
+            value.to_owned()
         } else {
             return false;
         };
@@ -101,8 +103,65 @@ fn version_info() -> VersionMeta {
         .expect("failed to determine underlying rustc version of Callgraph")
 }
 
+/// Gets the values of every occurrence of a `--flag`.
+fn get_arg_flag_values(name: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    // Stop searching at `--`.
+    let mut args = std::env::args().take_while(|val| val != "--");
+    while let Some(arg) = args.next() {
+        if !arg.starts_with(name) {
+            continue;
+        }
+        // Strip leading `name`.
+        let suffix = &arg[name.len()..];
+        if suffix.is_empty() {
+            // This argument is exactly `name`; the next one is the value.
+            values.extend(args.next());
+        } else if let Some(value) = suffix.strip_prefix('=') {
+            // This argument is `name=value`; get the value.
+            values.push(value.to_owned());
+        }
+    }
+    values
+}
+
+/// Which packages `cargo callgraph` analyzes.
+enum PackageSelection {
+    /// The package at `--manifest-path` or in the current directory.
+    Current,
+    /// Every member of the workspace (`--workspace`).
+    Workspace,
+    /// The members given with `-p`/`--package`.
+    Named(Vec<String>),
+}
+
+impl PackageSelection {
+    fn from_args() -> PackageSelection {
+        if has_arg_flag("--workspace") || has_arg_flag("--all") {
+            return PackageSelection::Workspace;
+        }
+        let mut names = get_arg_flag_values("-p");
+        names.extend(get_arg_flag_values("--package"));
+        if names.is_empty() {
+            PackageSelection::Current
+        } else {
+            PackageSelection::Named(names)
+        }
+    }
+
+    /// Whether `flag` selects packages. Returns whether the flag takes a value.
+    fn is_selection_flag(flag: &str) -> Option<bool> {
+        match flag {
+            "--workspace" | "--all" => Some(false),
+            "-p" | "--package" => Some(true),
+            _ if flag.starts_with("--package=") => Some(false),
+            _ => None,
+        }
+    }
+}
+
 //获取cargo包的元数据，用于获取目标包的信息，包括目标包的名称、路径、目标等信息，确定要分析哪些源文件和目标文件
-fn cargo_package() -> cargo_metadata::Package {
+fn cargo_packages(selection: &PackageSelection) -> (cargo_metadata::Metadata, Vec<cargo_metadata::Package>) {
     // We need to get the manifest, and then the metadata, to enumerate targets.
     let manifest_path =
         get_arg_flag_value("--manifest-path").map(|m| Path::new(&m).canonicalize().unwrap());
@@ -111,35 +170,54 @@ fn cargo_package() -> cargo_metadata::Package {
     if let Some(manifest_path) = &manifest_path {
         cmd.manifest_path(manifest_path);
     }
-    let mut metadata = match cmd.exec() {
+    let metadata = match cmd.exec() {
         Ok(metadata) => metadata,
         Err(e) => show_error(format!("Could not obtain Cargo metadata\n{}", e)),
     };
-    let current_dir = std::env::current_dir();
-
-    let package_index = metadata
-        .packages
-        .iter()
-        .position(|package| {
-            let package_manifest_path = Path::new(&package.manifest_path);
-            
-            if let Some(manifest_path) = &manifest_path {
-                package_manifest_path == manifest_path
-            } else {
-                let current_dir = current_dir
-                    .as_ref()
-                    .expect("could not read current directory");
-                let package_manifest_directory = package_manifest_path
-                    .parent()
-                    .expect("could not find parent directory of package manifest");
-                package_manifest_directory == current_dir
-            }
-        })
-        .unwrap_or_else(|| {
-            show_error("This seems to be a workspace, which is not supported by cargo-callgraph");
-        });
 
-    metadata.packages.remove(package_index)
+    let packages: Vec<_> = match selection {
+        PackageSelection::Workspace => metadata.workspace_packages().into_iter().cloned().collect(),
+        PackageSelection::Named(names) => names
+            .iter()
+            .map(|name| {
+                metadata
+                    .workspace_packages()
+                    .into_iter()
+                    .find(|package| &package.name == name)
+                    .cloned()
+                    .unwrap_or_else(|| show_error(format!("package `{}` is not a member of the workspace", name)))
+            })
+            .collect(),
+        PackageSelection::Current => {
+            let current_dir = std::env::current_dir();
+            let package = metadata
+                .packages
+                .iter()
+                .find(|package| {
+                    let package_manifest_path = Path::new(&package.manifest_path);
+
+                    if let Some(manifest_path) = &manifest_path {
+                        package_manifest_path == manifest_path
+                    } else {
+                        let current_dir = current_dir
+                            .as_ref()
+                            .expect("could not read current directory");
+                        let package_manifest_directory = package_manifest_path
+                            .parent()
+                            .expect("could not find parent directory of package manifest");
+                        package_manifest_directory == current_dir
+                    }
+                })
+                .unwrap_or_else(|| {
+                    show_error(
+                        "This seems to be a workspace, use `--workspace` or `-p <package>` to select the members to analyze",
+                    );
+                });
+            vec![package.clone()]
+        }
+    };
+
+    (metadata, packages)
 }
 
 /// Returns the path to the `callgraph` binary
@@ -205,7 +283,7 @@ fn clean_package(package_name: &str) {
         .expect("failed to wait for cargo?");
 
     if !exit_status.success() {
-        show_error(format!("cargo clean failed"));
+        show_error("cargo clean failed");
     }
 }
 
@@ -260,6 +338,9 @@ fn main() {
 enum TargetKind {
     Library = 0,
     Bin,
+    Test,
+    Example,
+    Bench,
     Unknown,
 }
 
@@ -275,8 +356,14 @@ impl From<&cargo_metadata::Target> for TargetKind {
     fn from(target: &cargo_metadata::Target) -> Self {
         if target.kind.iter().any(|s| TargetKind::is_lib_str(s)) {
             TargetKind::Library
-        } else if let Some("bin") = target.kind.get(0).map(|s| s.as_ref()) {
+        } else if let Some("bin") = target.kind.first().map(|s| s.as_ref()) {
             TargetKind::Bin
+        } else if let Some("test") = target.kind.first().map(|s| s.as_ref()) {
+            TargetKind::Test
+        } else if let Some("example") = target.kind.first().map(|s| s.as_ref()) {
+            TargetKind::Example
+        } else if let Some("bench") = target.kind.first().map(|s| s.as_ref()) {
+            TargetKind::Bench
         } else {
             TargetKind::Unknown
         }
@@ -291,6 +378,9 @@ impl Display for TargetKind {
             match self {
                 TargetKind::Library => "lib",
                 TargetKind::Bin => "bin",
+                TargetKind::Test => "test",
+                TargetKind::Example => "example",
+                TargetKind::Bench => "bench",
                 TargetKind::Unknown => "unknown",
             }
         )
@@ -298,6 +388,145 @@ impl Display for TargetKind {
 }
 
 
+/// Which targets besides libraries and binaries are analyzed.
+struct TargetSelection {
+    tests: bool,
+    examples: bool,
+    benches: bool,
+}
+
+impl TargetSelection {
+    fn from_args() -> TargetSelection {
+        let all = has_arg_flag("--all-targets");
+        TargetSelection {
+            tests: all || has_arg_flag("--tests"),
+            examples: all || has_arg_flag("--examples"),
+            benches: all || has_arg_flag("--benches"),
+        }
+    }
+
+    fn includes(&self, kind: &TargetKind) -> bool {
+        match kind {
+            TargetKind::Library | TargetKind::Bin => true,
+            TargetKind::Test => self.tests,
+            TargetKind::Example => self.examples,
+            TargetKind::Bench => self.benches,
+            TargetKind::Unknown => false,
+        }
+    }
+}
+
+/// Which dependencies `cargo callgraph` analyzes besides the selected packages (`--deps`).
+enum DepsSelection {
+    /// No dependency, the graph stops at the crate boundary.
+    None,
+    /// The dependencies of the selected packages.
+    Direct,
+    /// Every dependency, transitively.
+    All,
+    /// The dependencies with these package names, at any depth.
+    Named(Vec<String>),
+}
+
+impl DepsSelection {
+    fn from_args() -> DepsSelection {
+        match get_arg_flag_value("--deps").as_deref() {
+            None => DepsSelection::None,
+            Some("direct") => DepsSelection::Direct,
+            Some("all") => DepsSelection::All,
+            Some(names) => DepsSelection::Named(names.split(',').map(str::to_owned).collect()),
+        }
+    }
+
+    /// The dependencies of `packages` to analyze. Workspace members are not dependencies,
+    /// they are analyzed when selected. Build dependencies only run at build time.
+    fn select<'a>(
+        &self,
+        metadata: &'a cargo_metadata::Metadata,
+        packages: &[cargo_metadata::Package],
+    ) -> Vec<&'a cargo_metadata::Package> {
+        if let DepsSelection::None = self {
+            return Vec::new();
+        }
+        let selected = dependency_ids(metadata, packages, matches!(self, DepsSelection::Direct));
+
+        let members = &metadata.workspace_members;
+        let selected: Vec<&cargo_metadata::Package> = metadata
+            .packages
+            .iter()
+            .filter(|package| selected.contains(&&package.id) && !members.contains(&package.id))
+            .filter(|package| match self {
+                DepsSelection::Named(names) => names.contains(&package.name),
+                _ => true,
+            })
+            .collect();
+        if let DepsSelection::Named(names) = self {
+            for name in names {
+                if !selected.iter().any(|package| &package.name == name) {
+                    show_error(format!("package `{}` is not a dependency of the selected packages", name));
+                }
+            }
+        }
+        selected
+    }
+}
+
+/// The ids of the dependencies of `packages`, only the direct ones if `direct`, without
+/// build dependencies which only run at build time.
+fn dependency_ids<'a>(
+    metadata: &'a cargo_metadata::Metadata,
+    packages: &[cargo_metadata::Package],
+    direct: bool,
+) -> Vec<&'a cargo_metadata::PackageId> {
+    let resolve = metadata
+        .resolve
+        .as_ref()
+        .unwrap_or_else(|| show_error("Cargo metadata has no dependency graph"));
+    let nodes: HashMap<&cargo_metadata::PackageId, &cargo_metadata::Node> =
+        resolve.nodes.iter().map(|node| (&node.id, node)).collect();
+    let dependencies = |id: &cargo_metadata::PackageId| -> Vec<&'a cargo_metadata::PackageId> {
+        nodes.get(id).map_or(Vec::new(), |node| {
+            node.deps
+                .iter()
+                .filter(|dep| {
+                    dep.dep_kinds
+                        .iter()
+                        .any(|kind| kind.kind != cargo_metadata::DependencyKind::Build)
+                })
+                .map(|dep| &dep.pkg)
+                .collect()
+        })
+    };
+
+    let mut selected = Vec::new();
+    let mut stack: Vec<(&cargo_metadata::PackageId, usize)> = packages.iter().map(|package| (&package.id, 0)).collect();
+    let mut seen: HashSet<&cargo_metadata::PackageId> = stack.iter().map(|(id, _)| *id).collect();
+    while let Some((id, depth)) = stack.pop() {
+        if direct && depth == 1 {
+            continue;
+        }
+        for dep in dependencies(id) {
+            if seen.insert(dep) {
+                stack.push((dep, depth + 1));
+                selected.push(dep);
+            }
+        }
+    }
+    selected
+}
+
+/// Whether `arg` is handled by `cargo-callgraph` itself instead of being forwarded to
+/// `cargo check`, which is run for a single target at a time. Returns whether the flag
+/// takes a value.
+fn is_own_flag(arg: &str) -> Option<bool> {
+    match arg {
+        "--tests" | "--examples" | "--benches" | "--all-targets" => Some(false),
+        "--deps" => Some(true),
+        _ if arg.starts_with("--deps=") => Some(false),
+        _ => PackageSelection::is_selection_flag(arg),
+    }
+}
+
 //核心功能，编译项目，并收集调用关系，并生成调用图，dot文件，json文件，svg文件
 fn in_cargo_callgraph() {
     let verbose = has_arg_flag("-v");
@@ -309,19 +538,141 @@ fn in_cargo_callgraph() {
     //获取包的元数据
     //收集所有编译目标
     //按照类型排序：库(Library) 优先于二进制文件(Bin)，确保依赖关系正确
-    let package = cargo_package();
-    let mut targets: Vec<_> = package.targets.into_iter().collect();
+    let selection = PackageSelection::from_args();
+    let (metadata, packages) = cargo_packages(&selection);
+
+    // Arguments before `--` are for `cargo`, the rest is for Callgraph.
+    let mut cargo_args = Vec::new();
+    // Skip `cargo callgraph`
+    let mut args = std::env::args().skip(2);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        match is_own_flag(&arg) {
+            Some(true) => {
+                args.next();
+            }
+            Some(false) => {}
+            None => cargo_args.push(arg),
+        }
+    }
+    let mut callgraph_args: Vec<String> = args.collect();
+
+    // Every crate saves its graph, and we print the graph linking them in the requested format
+    // at the end: calls from a bin or test into its lib, or from one member of the workspace
+    // into another, resolve to the callee's definition and calls.
+    // The flags are still passed on, for the options of the analysis itself.
+    let config = Config::from_args(&mut callgraph_args.clone()).unwrap_or_else(|e| show_error(e));
+    let save_dir = match &config.save_dir {
+        Some(dir) => dir.clone(),
+        None => {
+            let save_dir = metadata.target_directory.join("callgraph").into_std_path_buf();
+            // Remove graphs of previous runs
+            if save_dir.exists() {
+                std::fs::remove_dir_all(&save_dir).expect("could not remove old call graphs");
+            }
+            callgraph_args.push("--save-dir".to_owned());
+            callgraph_args.push(save_dir.display().to_string());
+            save_dir
+        }
+    };
+
+    // The package ids of the workspace members by manifest directory, and the versions of all
+    // packages by crate name, for `callgraph` to identify the crates it analyzes and calls.
+    let package_ids: HashMap<String, String> = metadata
+        .workspace_packages()
+        .into_iter()
+        .filter_map(|package| Some((package.manifest_path.parent()?.to_string(), package.id.repr.clone())))
+        .collect();
+    let mut crate_versions: HashMap<String, Option<String>> = HashMap::new();
+    for package in &metadata.packages {
+        for target in package.targets.iter().filter(|target| matches!(TargetKind::from(*target), TargetKind::Library)) {
+            let version = package.version.to_string();
+            // a crate used in several versions has no version we can tell by its name
+            crate_versions
+                .entry(target.name.replace('-', "_"))
+                .and_modify(|known| {
+                    if known.as_ref() != Some(&version) {
+                        *known = None
+                    }
+                })
+                .or_insert(Some(version));
+        }
+    }
+    let crate_versions: HashMap<String, String> = crate_versions
+        .into_iter()
+        .filter_map(|(name, version)| Some((name, version?)))
+        .collect();
+
+    // The dependencies to analyze are rebuilt, so that Cargo runs us on them, and named to
+    // `inside_cargo_rustc` by crate name.
+    let dependencies = DepsSelection::from_args().select(&metadata, &packages);
+    let mut dependency_crates = Vec::new();
+    for package in &dependencies {
+        clean_package(&format!("{}@{}", package.name, package.version));
+        dependency_crates.extend(
+            package
+                .targets
+                .iter()
+                .filter(|target| matches!(TargetKind::from(*target), TargetKind::Library))
+                .map(|target| target.name.replace('-', "_")),
+        );
+    }
+
+    let callgraph_env = [
+        ("CALLGRAPH_PACKAGES", serde_json::to_string(&package_ids).expect("failed to serialize package ids")),
+        ("CALLGRAPH_CRATE_VERSIONS", serde_json::to_string(&crate_versions).expect("failed to serialize crate versions")),
+        ("CALLGRAPH_DEPS", serde_json::to_string(&dependency_crates).expect("failed to serialize dependencies")),
+    ];
+
+    // The workspace members the selected packages depend on are analyzed when Cargo builds
+    // them, which it only does when they are not fresh.
+    let member_dependencies = dependency_ids(&metadata, &packages, false);
+    for package in metadata.workspace_packages() {
+        if member_dependencies.contains(&&package.id) && !packages.iter().any(|selected| selected.id == package.id) {
+            clean_package(&format!("{}@{}", package.name, package.version));
+        }
+    }
+
+    let target_selection = TargetSelection::from_args();
+    let select_package = !matches!(selection, PackageSelection::Current);
+    for package in &packages {
+        analyze_package(package, &target_selection, &cargo_args, &callgraph_args, &callgraph_env, select_package, verbose);
+    }
+
+    let name = match packages.as_slice() {
+        [package] => package.name.clone(),
+        _ => metadata.workspace_root.file_name().unwrap_or("workspace").to_owned(),
+    };
+    let graph = link_saved(name, &save_dir).unwrap_or_else(|e| show_error(e));
+    emit(&graph, &config);
+}
+
+// Runs `cargo check` with ourselves as the rustc wrapper for every target of `package`.
+fn analyze_package(
+    package: &cargo_metadata::Package,
+    target_selection: &TargetSelection,
+    cargo_args: &[String],
+    callgraph_args: &[String],
+    callgraph_env: &[(&str, String)],
+    select_package: bool,
+    verbose: bool,
+) {
+    // Clean the results of every target to disable Cargo's freshness check, the graphs
+    // of the previous run are gone
+    clean_package(&package.name);
+
+    let mut targets = package.targets.clone();
 
     // Ensure `lib` is compiled before `bin`
     targets.sort_by_key(|target| TargetKind::from(target) as u8);
 
     //遍历所有编译目标，对不同类型的目标采用不同的编译策略
     for target in targets {
-        // Skip `cargo callgraph`
-        let mut args = std::env::args().skip(2);
         let kind = TargetKind::from(&target);
 
-        println!("Target name: {}", &target.name);
+        eprintln!("Target name: {}", &target.name);
 
         // Now we run `cargo check $FLAGS $ARGS`, giving the user the
         // change to add additional arguments. `FLAGS` is set to identify
@@ -337,17 +688,33 @@ fn in_cargo_callgraph() {
             TargetKind::Library => {
                 // There can be only one lib in a crate.
                 cmd.arg("--lib");
-                // Clean the result to disable Cargo's freshness check
-                clean_package(&package.name);
             }
-            TargetKind::Unknown => {
-                println!(
-                    "Target {}:{} is not supported",
+            _ if !target_selection.includes(&kind) => {
+                eprintln!(
+                    "Target {}:{} is not supported or not selected",
                     target.kind.as_slice().join("/"),
                     &target.name
                 );
                 continue;
             }
+            TargetKind::Test => {
+                cmd.arg("--test").arg(&target.name);
+            }
+            TargetKind::Example => {
+                cmd.arg("--example").arg(&target.name);
+            }
+            TargetKind::Bench => {
+                cmd.arg("--bench").arg(&target.name);
+            }
+            TargetKind::Unknown => unreachable!("unknown targets are never selected"),
+        }
+
+        // Tells `callgraph` which target it is analyzing, so that it can tag the functions.
+        cmd.env("CALLGRAPH_TARGET", format!("{}:{}", kind, target.name));
+        cmd.envs(callgraph_env.iter().map(|(key, value)| (key, value)));
+
+        if select_package {
+            cmd.arg("-p").arg(&package.name);
         }
 
         if !cfg!(debug_assertions) && !verbose {
@@ -355,12 +722,7 @@ fn in_cargo_callgraph() {
         }
 
         // Forward user-defined `cargo` args until first `--`.
-        while let Some(arg) = args.next() {
-            if arg == "--" {
-                break;
-            }
-            cmd.arg(arg);
-        }
+        cmd.args(cargo_args);
 
         // We want to always run `cargo` with `--target`. This later helps us detect
         // which crates are proc-macro/build-script (host crates) and which crates are
@@ -376,10 +738,9 @@ fn in_cargo_callgraph() {
         // our actual target crate (the binary or the test we are running).
         // Since we're using "cargo check", we have no other way of passing
         // these arguments.
-        let args_vec: Vec<String> = args.collect();
         cmd.env(
             "CALLGRAPH_ARGS",
-            serde_json::to_string(&args_vec).expect("failed to serialize args"),
+            serde_json::to_string(callgraph_args).expect("failed to serialize args"),
         );
 
         
@@ -485,8 +846,20 @@ fn inside_cargo_rustc() {
 
     // TODO: Miri sets custom sysroot here, check if it is needed for us (CALLGRAPH-30)
 
+    // A dependency selected with `--deps`.
+    fn is_selected_dependency() -> bool {
+        let Some(crate_name) = get_arg_flag_value("--crate-name") else {
+            return false;
+        };
+        let dependencies: Vec<String> = match std::env::var("CALLGRAPH_DEPS") {
+            Ok(deps) => serde_json::from_str(&deps).expect("failed to deserialize CALLGRAPH_DEPS"),
+            Err(_) => return false,
+        };
+        dependencies.contains(&crate_name)
+    }
+
     let is_direct_target = contains_target_flag() && is_target_crate();
-    let is_additional_target = false;
+    let is_additional_target = contains_target_flag() && !is_direct_target && is_selected_dependency();
 
     if is_direct_target || is_additional_target {
         let mut cmd = Command::new(find_callgraph());
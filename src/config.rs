@@ -0,0 +1,242 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// How the call graph of the analyzed crate is printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Human readable listing, see `CallGraph::dump`.
+    #[default]
+    Text,
+    /// The versioned schema of `CallGraph`.
+    Json,
+    /// A Graphviz digraph, see `dot::render`.
+    Dot,
+    /// Comma separated values, for the `--metrics` report only.
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "dot" => Ok(OutputFormat::Dot),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("unknown output format `{}`, expected `text`, `json`, `dot` or `csv`", s)),
+        }
+    }
+}
+
+/// How nodes are grouped in the DOT output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Cluster {
+    Crate,
+    /// Module clusters nested inside crate clusters.
+    #[default]
+    Module,
+}
+
+impl FromStr for Cluster {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "crate" => Ok(Cluster::Crate),
+            "module" => Ok(Cluster::Module),
+            _ => Err(format!("unknown cluster `{}`, expected `crate` or `module`", s)),
+        }
+    }
+}
+
+/// Where the calls are read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Method calls and calls of paths in the HIR, with the conditions guarding them.
+    #[default]
+    Hir,
+    /// `Call` and `Drop` terminators of the MIR, resolved with concrete generic arguments.
+    Mir,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hir" => Ok(Backend::Hir),
+            "mir" => Ok(Backend::Mir),
+            _ => Err(format!("unknown backend `{}`, expected `hir` or `mir`", s)),
+        }
+    }
+}
+
+/// How dynamic calls of trait methods are expanded to the implementations they may call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DynResolution {
+    /// Only the call to the trait method declaration is recorded.
+    #[default]
+    None,
+    /// Class hierarchy analysis: every local implementation of the method.
+    Cha,
+    /// Rapid type analysis: implementations for types that are coerced to `dyn Trait`.
+    Rta,
+}
+
+impl FromStr for DynResolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(DynResolution::None),
+            "cha" => Ok(DynResolution::Cha),
+            "rta" => Ok(DynResolution::Rta),
+            _ => Err(format!("unknown dyn resolution `{}`, expected `none`, `cha` or `rta`", s)),
+        }
+    }
+}
+
+/// Where the reachability analysis of `--roots` starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Root {
+    /// The `main` function of binaries.
+    Main,
+    /// Every function of the public API of libraries.
+    Public,
+    /// A function given by its def path, e.g. `mycrate::parser::parse`.
+    Path(String),
+}
+
+impl FromStr for Root {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err("empty root in `--roots`".to_owned()),
+            "main" => Ok(Root::Main),
+            "pub" => Ok(Root::Public),
+            _ => Ok(Root::Path(s.to_owned())),
+        }
+    }
+}
+
+/// Options understood by the `callgraph` driver on top of the regular rustc flags.
+/// Their names must not clash with rustc flags, which Cargo passes first.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub format: OutputFormat,
+    pub cluster: Cluster,
+    /// Save the graph as JSON into this directory instead of printing it.
+    /// `cargo-callgraph` uses this to link the graphs of its crates, see `callgraph link`.
+    pub save_dir: Option<PathBuf>,
+    pub dyn_resolution: DynResolution,
+    pub backend: Backend,
+    /// Print the functions reachable from these roots instead of the graph.
+    pub roots: Vec<Root>,
+    /// Print the recursion cycles instead of the graph.
+    pub recursion: bool,
+    /// Print the calls and functions that only run when failing instead of the graph.
+    pub failure_paths: bool,
+    /// Print which functions may panic instead of the graph.
+    pub panics: bool,
+    /// Print the unsafe code and the safe public API reaching it instead of the graph.
+    pub unsafe_reach: bool,
+    /// Print per function metrics instead of the graph. Depths start at `roots`.
+    pub metrics: bool,
+}
+
+impl Config {
+    /// Parses and removes the callgraph flags from `args`, so that only the
+    /// arguments meant for rustc are left behind.
+    pub fn from_args(args: &mut Vec<String>) -> Result<Config, String> {
+        let mut config = Config::default();
+        if let Some(format) = take_flag_value(args, "--format")? {
+            config.format = format.parse()?;
+        }
+        if let Some(cluster) = take_flag_value(args, "--cluster")? {
+            config.cluster = cluster.parse()?;
+        }
+        if let Some(save_dir) = take_flag_value(args, "--save-dir")? {
+            config.save_dir = Some(PathBuf::from(save_dir));
+        }
+        if let Some(dyn_resolution) = take_flag_value(args, "--dyn-resolution")? {
+            config.dyn_resolution = dyn_resolution.parse()?;
+        }
+        if let Some(backend) = take_flag_value(args, "--backend")? {
+            config.backend = backend.parse()?;
+        }
+        if let Some(roots) = take_flag_value(args, "--roots")? {
+            config.roots = roots.split(',').map(str::parse).collect::<Result<_, _>>()?;
+        }
+        config.recursion = take_flag(args, "--recursion");
+        config.failure_paths = take_flag(args, "--failure-paths");
+        config.panics = take_flag(args, "--panics");
+        config.unsafe_reach = take_flag(args, "--unsafe");
+        config.metrics = take_flag(args, "--metrics");
+        // `--roots` also sets where the depths of `--metrics` start
+        let reports = [
+            !config.roots.is_empty() && !config.metrics,
+            config.recursion,
+            config.failure_paths,
+            config.panics,
+            config.unsafe_reach,
+            config.metrics,
+        ];
+        if reports.iter().filter(|report| **report).count() > 1 {
+            return Err(
+                "only one of `--roots`, `--recursion`, `--failure-paths`, `--panics`, `--unsafe` and `--metrics` can be given"
+                    .to_owned(),
+            );
+        }
+        if config.whole_program() && config.format == OutputFormat::Dot {
+            return Err("reports cannot be printed as DOT, use `--format text` or `--format json`".to_owned());
+        }
+        if config.format == OutputFormat::Csv && !config.metrics {
+            return Err("`--format csv` is only supported for `--metrics`".to_owned());
+        }
+        Ok(config)
+    }
+
+    /// Whether the output needs the graph of every crate of the workspace at once,
+    /// rather than one graph per crate.
+    pub fn whole_program(&self) -> bool {
+        !self.roots.is_empty() || self.recursion || self.failure_paths || self.panics || self.unsafe_reach || self.metrics
+    }
+}
+
+/// Removes the flag `name` from `args` and returns whether it was there.
+/// Stops searching at `--`.
+pub(crate) fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let end = args.iter().position(|arg| arg == "--").unwrap_or(args.len());
+    match args[..end].iter().position(|arg| arg == name) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Removes `name value` or `name=value` from `args` and returns the value.
+/// Stops searching at `--`.
+pub(crate) fn take_flag_value(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let mut i = 0;
+    while i < args.len() && args[i] != "--" {
+        if args[i] == name {
+            if i + 1 >= args.len() {
+                return Err(format!("missing value for `{}`", name));
+            }
+            let value = args.remove(i + 1);
+            args.remove(i);
+            return Ok(Some(value));
+        }
+        if let Some(value) = args[i].strip_prefix(name).and_then(|s| s.strip_prefix('=')) {
+            let value = value.to_owned();
+            args.remove(i);
+            return Ok(Some(value));
+        }
+        i += 1;
+    }
+    Ok(None)
+}
@@ -0,0 +1,317 @@
+//! `callgraph diff`: what changed between two call graphs saved with `--format json`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{take_flag_value, OutputFormat};
+use crate::graph::{CallGraph, CallKind, CallSite, Function};
+use crate::print_json;
+use crate::query::load_graph;
+
+/// Runs `callgraph diff <old.json> <new.json> [--format text|json]`.
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let format = match take_flag_value(&mut args, "--format")? {
+        Some(format) => format.parse()?,
+        None => OutputFormat::Text,
+    };
+    let [old, new] = args.as_slice() else {
+        return Err("expected two call graphs: `callgraph diff <old.json> <new.json>`".to_owned());
+    };
+    let diff = GraphDiff::new(&load_graph(Some(old))?, &load_graph(Some(new))?);
+    match format {
+        OutputFormat::Text => diff.dump(),
+        OutputFormat::Json => print_json(&diff),
+        _ => return Err("`callgraph diff` prints a report, use `--format text` or `--format json`".to_owned()),
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphDiff {
+    pub added_functions: Vec<String>,
+    pub removed_functions: Vec<String>,
+    pub added_calls: Vec<Edge>,
+    pub removed_calls: Vec<Edge>,
+    /// Calls in both graphs whose kinds or constraint depth changed.
+    pub changed_calls: Vec<ChangedEdge>,
+    /// Functions of other crates called in the new graph but not in the old one, e.g. a
+    /// new route into `std::process`.
+    pub added_dependencies: Vec<String>,
+}
+
+/// All calls from `caller` to `callee`, whatever their kind.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Edge {
+    pub caller: String,
+    pub callee: String,
+    pub kinds: Vec<CallKind>,
+    /// The smallest constraint depth of the calls.
+    pub constraint_depth: usize,
+    pub sites: Vec<CallSite>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangedEdge {
+    pub old: Edge,
+    pub new: Edge,
+}
+
+impl GraphDiff {
+    /// Functions and calls are matched by def path. Functions sharing a def path in either
+    /// graph, e.g. those of two versions of a crate, are told apart by their target and
+    /// crate version, or by their id if that is not enough.
+    pub fn new(old: &CallGraph, new: &CallGraph) -> GraphDiff {
+        // def paths of more than one function, then those not told apart by target and version
+        let shared = duplicates([old, new], |_, function| Some(function.def_path.clone()));
+        let ambiguous = duplicates([old, new], |graph, function| {
+            shared.contains(&function.def_path).then(|| qualified_name(graph, function))
+        });
+        let names = |graph| function_names(graph, &shared, &ambiguous);
+        let (old_names, new_names) = (names(old), names(new));
+        let functions =
+            |names: &HashMap<&str, String>| -> BTreeSet<String> { names.values().cloned().collect() };
+        let (old_functions, new_functions) = (functions(&old_names), functions(&new_names));
+        let (old_edges, new_edges) = (edges(old, &old_names), edges(new, &new_names));
+
+        let mut changed_calls = Vec::new();
+        for (key, new_edge) in &new_edges {
+            if let Some(old_edge) = old_edges.get(key) {
+                if old_edge.kinds != new_edge.kinds || old_edge.constraint_depth != new_edge.constraint_depth {
+                    changed_calls.push(ChangedEdge { old: old_edge.clone(), new: new_edge.clone() });
+                }
+            }
+        }
+
+        let dependencies = |graph: &CallGraph, names: &HashMap<&str, String>| -> BTreeSet<String> {
+            graph
+                .calls
+                .iter()
+                .filter(|call| !names.contains_key(call.callee_id.as_str()) && call.callee_crate != call.caller_crate)
+                .map(|call| call.callee.clone())
+                .collect()
+        };
+        let old_dependencies = dependencies(old, &old_names);
+
+        GraphDiff {
+            added_functions: new_functions.difference(&old_functions).cloned().collect(),
+            removed_functions: old_functions.difference(&new_functions).cloned().collect(),
+            added_calls: new_edges
+                .iter()
+                .filter(|(key, _)| !old_edges.contains_key(*key))
+                .map(|(_, edge)| edge.clone())
+                .collect(),
+            removed_calls: old_edges
+                .iter()
+                .filter(|(key, _)| !new_edges.contains_key(*key))
+                .map(|(_, edge)| edge.clone())
+                .collect(),
+            changed_calls,
+            added_dependencies: dependencies(new, &new_names)
+                .difference(&old_dependencies)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Prints the human readable report used by `--format text`.
+    pub fn dump(&self) {
+        println!("Added Functions ({}):", self.added_functions.len());
+        for def_path in &self.added_functions {
+            println!("  + {}", def_path);
+        }
+
+        println!("\nRemoved Functions ({}):", self.removed_functions.len());
+        for def_path in &self.removed_functions {
+            println!("  - {}", def_path);
+        }
+
+        println!("\nAdded Calls ({}):", self.added_calls.len());
+        for edge in &self.added_calls {
+            println!("  + {}", edge);
+            for site in &edge.sites {
+                if let Some(span) = &site.span {
+                    println!("      at {}", span);
+                }
+            }
+        }
+
+        println!("\nRemoved Calls ({}):", self.removed_calls.len());
+        for edge in &self.removed_calls {
+            println!("  - {}", edge);
+        }
+
+        println!("\nChanged Calls ({}):", self.changed_calls.len());
+        for change in &self.changed_calls {
+            println!("  - {}", change.old);
+            println!("  + {}", change.new);
+        }
+
+        println!("\nAdded Dependencies ({}):", self.added_dependencies.len());
+        for def_path in &self.added_dependencies {
+            println!("  + {}", def_path);
+        }
+    }
+}
+
+impl std::fmt::Display for Edge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kinds: Vec<String> = self.kinds.iter().map(CallKind::to_string).collect();
+        write!(
+            f,
+            "{} --- {} ({}, Constraint Depth: {})",
+            self.caller,
+            self.callee,
+            kinds.join(", "),
+            self.constraint_depth
+        )
+    }
+}
+
+// The def path of `function` with its target and crate version.
+fn qualified_name(graph: &CallGraph, function: &Function) -> String {
+    let version = graph.crate_of(function).and_then(|identity| identity.version.as_ref());
+    let qualifiers: Vec<&str> = function.target.iter().chain(version).map(String::as_str).collect();
+    format!("{} ({})", function.def_path, qualifiers.join(", "))
+}
+
+// The names `name` gives to more than one function of a graph.
+fn duplicates(graphs: [&CallGraph; 2], name: impl Fn(&CallGraph, &Function) -> Option<String>) -> BTreeSet<String> {
+    let mut duplicates = BTreeSet::new();
+    for graph in graphs {
+        let mut seen = BTreeSet::new();
+        for name in graph.functions.iter().filter_map(|function| name(graph, function)) {
+            if !seen.insert(name.clone()) {
+                duplicates.insert(name);
+            }
+        }
+    }
+    duplicates
+}
+
+// The names of the functions of `graph` by id: their def paths, qualified by target and
+// crate version if they are `shared`, or by id if that is still `ambiguous`.
+fn function_names<'a>(
+    graph: &'a CallGraph,
+    shared: &BTreeSet<String>,
+    ambiguous: &BTreeSet<String>,
+) -> HashMap<&'a str, String> {
+    graph
+        .functions
+        .iter()
+        .map(|function| {
+            let name = if !shared.contains(&function.def_path) {
+                function.def_path.clone()
+            } else {
+                let qualified = qualified_name(graph, function);
+                if ambiguous.contains(&qualified) {
+                    format!("{} ({})", function.def_path, function.id)
+                } else {
+                    qualified
+                }
+            };
+            (function.id.as_str(), name)
+        })
+        .collect()
+}
+
+// The calls of `graph` by caller and callee, named by `names` if they are functions of the graph.
+fn edges(graph: &CallGraph, names: &HashMap<&str, String>) -> BTreeMap<(String, String), Edge> {
+    let name = |id: &str, def_path: &String| names.get(id).unwrap_or(def_path).clone();
+    let mut edges: BTreeMap<(String, String), Edge> = BTreeMap::new();
+    for call in &graph.calls {
+        let (caller, callee) = (name(&call.caller_id, &call.caller), name(&call.callee_id, &call.callee));
+        let edge = edges.entry((caller.clone(), callee.clone())).or_insert_with(|| Edge {
+            caller,
+            callee,
+            kinds: Vec::new(),
+            constraint_depth: call.constraint_depth,
+            sites: Vec::new(),
+        });
+        if !edge.kinds.contains(&call.kind) {
+            edge.kinds.push(call.kind);
+            edge.kinds.sort();
+        }
+        edge.constraint_depth = edge.constraint_depth.min(call.constraint_depth);
+        for site in &call.sites {
+            if !edge.sites.contains(site) {
+                edge.sites.push(site.clone());
+            }
+        }
+    }
+    edges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{call, function, graph};
+
+    fn names(edges: &[Edge]) -> Vec<(&str, &str)> {
+        edges.iter().map(|edge| (edge.caller.as_str(), edge.callee.as_str())).collect()
+    }
+
+    #[test]
+    fn added_removed_and_changed() {
+        let old = graph(
+            &["a::f", "a::g", "a::old"],
+            vec![call("a::f", "a::g", 1), call("a::f", "a::old", 2), call("a::g", "std::fs::read", 3)],
+        );
+        let mut guarded = call("a::f", "a::g", 1);
+        guarded.constraint_depth = 1;
+        let new = graph(
+            &["a::f", "a::g", "a::new"],
+            vec![guarded, call("a::f", "a::new", 2), call("a::new", "std::process::exit", 3)],
+        );
+
+        let diff = GraphDiff::new(&old, &new);
+        assert_eq!(diff.added_functions, ["a::new"]);
+        assert_eq!(diff.removed_functions, ["a::old"]);
+        assert_eq!(names(&diff.added_calls), [("a::f", "a::new"), ("a::new", "std::process::exit")]);
+        assert_eq!(names(&diff.removed_calls), [("a::f", "a::old"), ("a::g", "std::fs::read")]);
+        assert_eq!(diff.changed_calls.len(), 1);
+        assert_eq!((diff.changed_calls[0].old.constraint_depth, diff.changed_calls[0].new.constraint_depth), (0, 1));
+        assert_eq!(diff.added_dependencies, ["std::process::exit"]);
+    }
+
+    #[test]
+    fn functions_sharing_a_def_path() {
+        // two versions of `util`, the old graph calls both and the new one only the second
+        let util = |id: &str| {
+            let mut function = function("util::f");
+            function.id = id.to_owned();
+            function.target = Some("lib:util".to_owned());
+            function
+        };
+        let call_of = |caller: &str, id: &str| {
+            let mut call = call(caller, "util::f", 1);
+            call.callee_id = id.to_owned();
+            call
+        };
+        let mut old = graph(&["a::f", "a::g"], vec![call_of("a::f", "util-1"), call_of("a::g", "util-2")]);
+        old.functions.extend([util("util-1"), util("util-2")]);
+        let mut new = graph(&["a::f", "a::g"], vec![call_of("a::g", "util-2")]);
+        new.functions.push(util("util-2"));
+
+        let diff = GraphDiff::new(&old, &new);
+        assert_eq!(diff.removed_functions, ["util::f (util-1)"]);
+        assert!(diff.added_functions.is_empty());
+        assert_eq!(names(&diff.removed_calls), [("a::f", "util::f (util-1)")]);
+        assert!(diff.added_calls.is_empty());
+    }
+
+    #[test]
+    fn calls_of_several_kinds_form_one_edge() {
+        let old = graph(&["a::f", "a::g"], vec![call("a::f", "a::g", 1)]);
+        let mut dynamic = call("a::f", "a::g", 2);
+        dynamic.kind = CallKind::Dynamic;
+        let new = graph(&["a::f", "a::g"], vec![call("a::f", "a::g", 1), dynamic]);
+
+        let diff = GraphDiff::new(&old, &new);
+        assert!(diff.added_calls.is_empty());
+        assert_eq!(diff.changed_calls.len(), 1);
+        assert_eq!(diff.changed_calls[0].new.kinds, [CallKind::Static, CallKind::Dynamic]);
+        assert_eq!(diff.changed_calls[0].new.sites.len(), 2);
+        assert!(GraphDiff::new(&new, &new).changed_calls.is_empty());
+    }
+}
@@ -0,0 +1,118 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::config::Cluster;
+use crate::graph::{CallGraph, CallKind};
+
+// module -> nodes as (def path, id, label)
+type Modules<'a> = BTreeMap<&'a str, BTreeSet<(&'a str, &'a str, &'a str)>>;
+
+/// Renders the call graph as a Graphviz `digraph`.
+///
+/// Every function of the analyzed crate becomes a node inside a cluster for its
+/// crate, optionally nested in a cluster for its module. Callees from other crates
+/// only get a crate cluster, since we know nothing about their modules. Nodes are named
+/// by function id and labeled with the def path.
+pub fn render(graph: &CallGraph, cluster: Cluster) -> String {
+    let mut clusters: BTreeMap<&str, Modules> = BTreeMap::new();
+    let mut known = BTreeSet::new();
+
+    for function in graph.functions.iter().chain(&graph.method_decls) {
+        if !known.insert(function.id.as_str()) {
+            // provided trait methods are both a function and a decl
+            continue;
+        }
+        let module = match cluster {
+            Cluster::Crate => function.krate.as_str(),
+            Cluster::Module => function.module.as_str(),
+        };
+        clusters
+            .entry(function.krate.as_str())
+            .or_default()
+            .entry(module)
+            .or_default()
+            .insert((&function.def_path, &function.id, label(&function.def_path, module)));
+    }
+
+    for call in &graph.calls {
+        for (path, id, krate) in [
+            (&call.caller, &call.caller_id, &call.caller_crate),
+            (&call.callee, &call.callee_id, &call.callee_crate),
+        ] {
+            if path.is_empty() || !known.insert(id.as_str()) {
+                continue;
+            }
+            clusters
+                .entry(krate.as_str())
+                .or_default()
+                .entry(krate.as_str())
+                .or_default()
+                .insert((path, id, label(path, krate)));
+        }
+    }
+
+    let mut out = String::new();
+    writeln!(out, "digraph callgraph {{").unwrap();
+    writeln!(out, "    compound=true;").unwrap();
+    writeln!(out, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+
+    for (i, (krate, modules)) in clusters.iter().enumerate() {
+        writeln!(out, "    subgraph \"cluster_{}\" {{", i).unwrap();
+        writeln!(out, "        label={};", quote(krate)).unwrap();
+        for (j, (module, nodes)) in modules.iter().enumerate() {
+            // the crate root does not need a cluster of its own
+            let nested = module != krate;
+            let indent = if nested { "            " } else { "        " };
+            if nested {
+                writeln!(out, "        subgraph \"cluster_{}_{}\" {{", i, j).unwrap();
+                writeln!(out, "            label={};", quote(module)).unwrap();
+            }
+            for (_, id, label) in nodes {
+                writeln!(out, "{}{} [label={}];", indent, quote(id), quote(label)).unwrap();
+            }
+            if nested {
+                writeln!(out, "        }}").unwrap();
+            }
+        }
+        writeln!(out, "    }}").unwrap();
+    }
+
+    for call in &graph.calls {
+        if call.caller.is_empty() {
+            continue;
+        }
+        let style = match call.kind {
+            _ if call.may_call => "style=dashed, color=darkorange",
+            CallKind::Static => "style=solid, color=black",
+            CallKind::Dynamic => "style=dashed, color=blue",
+            CallKind::NonLocal => "style=dotted, color=gray40",
+        };
+        // only label edges with more than one call site to keep the graph readable
+        let label = if call.count > 1 { format!(", label=\"{}\"", call.count) } else { String::new() };
+        writeln!(
+            out,
+            "    {} -> {} [{}{}, tooltip=\"{} call site(s), constraint depth {}\"];",
+            quote(&call.caller_id),
+            quote(&call.callee_id),
+            style,
+            label,
+            call.count,
+            call.constraint_depth
+        )
+        .unwrap();
+    }
+
+    writeln!(out, "}}").unwrap();
+    out
+}
+
+// Strips the cluster prefix from the node label, it is already shown on the cluster.
+fn label<'a>(path: &'a str, prefix: &str) -> &'a str {
+    path.strip_prefix(prefix)
+        .and_then(|rest| rest.strip_prefix("::"))
+        .unwrap_or(path)
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,265 @@
+//! Calls and functions that only run when failing, for `--failure-paths`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Root;
+use crate::graph::{CallGraph, CallSite, ControlPath, Location};
+use crate::reach::select_roots;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailurePaths {
+    /// The calls with sites on the error or panic path, with only those sites.
+    pub calls: Vec<FailureCall>,
+    /// Functions of the graph that are only reached through calls on the error or panic path.
+    pub functions: Vec<FailureFunction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailureCall {
+    pub caller: String,
+    pub callee: String,
+    pub sites: Vec<CallSite>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailureFunction {
+    pub def_path: String,
+    /// `error` if the function can be reached without panicking, `panic` otherwise.
+    pub path: ControlPath,
+    pub span: Option<Location>,
+}
+
+impl FailurePaths {
+    /// Functions are reached from the `main` functions, the public API, the implementations
+    /// of traits from other crates and the functions nobody calls.
+    pub fn new(graph: &CallGraph) -> FailurePaths {
+        // by def paths first, for the order of the report
+        let mut calls: BTreeMap<(&str, &str, &str, &str), FailureCall> = BTreeMap::new();
+        // the most common path of the calls between two functions
+        let mut edges: HashMap<&str, BTreeMap<&str, ControlPath>> = HashMap::new();
+        for call in &graph.calls {
+            let failing: Vec<&CallSite> = call.sites.iter().filter(|site| site.path != ControlPath::Normal).collect();
+            if !failing.is_empty() {
+                let entry = calls.entry((&call.caller, &call.callee, &call.caller_id, &call.callee_id)).or_insert_with(|| FailureCall {
+                    caller: call.caller.clone(),
+                    callee: call.callee.clone(),
+                    sites: Vec::new(),
+                });
+                for site in failing {
+                    if !entry.sites.contains(site) {
+                        entry.sites.push(site.clone());
+                    }
+                }
+            }
+            if call.caller.is_empty() {
+                continue;
+            }
+            let path = call.sites.iter().map(|site| site.path).min().unwrap_or_default();
+            let edge = edges.entry(&call.caller_id).or_default().entry(&call.callee_id).or_insert(path);
+            *edge = (*edge).min(path);
+        }
+        // closures run where they are passed to, trait methods in their implementations; a
+        // closure that is only returned or stored runs where it is called, like in `hops`
+        let links = graph
+            .functions
+            .iter()
+            .filter(|function| function.passed_on)
+            .filter_map(|function| Some((function.parent_id.as_deref()?, function.id.as_str())))
+            .chain(graph.method_impls.iter().flat_map(|method_impls| {
+                method_impls.impl_ids.iter().map(|id| (method_impls.decl_id.as_str(), id.as_str()))
+            }));
+        for (from, to) in links {
+            edges.entry(from).or_default().insert(to, ControlPath::Normal);
+        }
+
+        let called: BTreeSet<&str> = edges.values().flat_map(|callees| callees.keys().copied()).collect();
+        let local_decls: BTreeSet<&str> = graph.method_decls.iter().map(|decl| decl.id.as_str()).collect();
+        let mut roots = select_roots(graph, &[Root::Main, Root::Public]);
+        roots.extend(
+            graph
+                .functions
+                .iter()
+                .map(|function| function.id.as_str())
+                .filter(|id| !called.contains(id)),
+        );
+        roots.extend(
+            graph
+                .method_impls
+                .iter()
+                .filter(|method_impls| !local_decls.contains(method_impls.decl_id.as_str()))
+                .flat_map(|method_impls| method_impls.impl_ids.iter().map(String::as_str)),
+        );
+
+        // the functions reached by following the calls up to `path`
+        let reach = |path: ControlPath| -> BTreeSet<&str> {
+            let mut reached = BTreeSet::new();
+            let mut stack: Vec<&str> = roots.iter().copied().collect();
+            while let Some(id) = stack.pop() {
+                if reached.insert(id) {
+                    let callees = edges.get(id).into_iter().flatten();
+                    stack.extend(callees.filter(|(_, edge)| **edge <= path).map(|(callee, _)| *callee));
+                }
+            }
+            reached
+        };
+        let normal = reach(ControlPath::Normal);
+        let error = reach(ControlPath::Error);
+        let panic = reach(ControlPath::Panic);
+
+        let mut functions: Vec<FailureFunction> = graph
+            .functions
+            .iter()
+            .filter(|function| !normal.contains(function.id.as_str()))
+            .filter_map(|function| {
+                let path = if error.contains(function.id.as_str()) {
+                    ControlPath::Error
+                } else if panic.contains(function.id.as_str()) {
+                    ControlPath::Panic
+                } else {
+                    return None;
+                };
+                Some(FailureFunction { def_path: function.def_path.clone(), path, span: function.span.clone() })
+            })
+            .collect();
+        functions.sort_by(|a, b| a.def_path.cmp(&b.def_path));
+
+        FailurePaths { calls: calls.into_values().collect(), functions }
+    }
+
+    /// Prints the human readable report used by `--format text`.
+    pub fn dump(&self) {
+        println!("Calls Only On Failure ({}):", self.calls.len());
+        for call in &self.calls {
+            println!("  {} --- {}", call.caller, call.callee);
+            for site in &call.sites {
+                if let Some(span) = &site.span {
+                    println!("      at {} ({})", span, site.path);
+                }
+            }
+        }
+
+        println!("\nFunctions Only Reached On Failure ({}):", self.functions.len());
+        for function in &self.functions {
+            match &function.span {
+                Some(span) => println!("  {} ({}), Span: {}", function.def_path, function.path, span),
+                None => println!("  {} ({})", function.def_path, function.path),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{Call, FunctionKind};
+    use crate::testing::{call, graph, id, method_impls};
+
+    fn on(path: ControlPath, mut call: Call) -> Call {
+        call.sites[0].path = path;
+        call
+    }
+
+    // the failure-only functions with their paths
+    fn functions(graph: &CallGraph) -> Vec<(String, ControlPath)> {
+        FailurePaths::new(graph).functions.into_iter().map(|function| (function.def_path, function.path)).collect()
+    }
+
+    /// `a::main` is the only root, the other functions are called by it.
+    fn program(functions: &[&str], calls: Vec<Call>) -> CallGraph {
+        let mut graph = graph(functions, calls);
+        for function in &mut graph.functions {
+            function.public = false;
+            function.entry = function.def_path == "a::main";
+        }
+        graph
+    }
+
+    #[test]
+    fn error_panic_and_normal_paths() {
+        let graph = program(
+            &["a::main", "a::run", "a::recover", "a::log", "a::abort", "a::both"],
+            vec![
+                call("a::main", "a::run", 1),
+                on(ControlPath::Error, call("a::main", "a::recover", 2)),
+                call("a::recover", "a::log", 3),
+                on(ControlPath::Panic, call("a::main", "a::abort", 4)),
+                call("a::abort", "a::both", 5),
+                call("a::recover", "a::both", 6),
+            ],
+        );
+        assert_eq!(
+            functions(&graph),
+            [
+                ("a::abort".to_owned(), ControlPath::Panic),
+                ("a::both".to_owned(), ControlPath::Error),
+                ("a::log".to_owned(), ControlPath::Error),
+                ("a::recover".to_owned(), ControlPath::Error),
+            ]
+        );
+        let calls: Vec<(String, String)> =
+            FailurePaths::new(&graph).calls.into_iter().map(|call| (call.caller, call.callee)).collect();
+        assert_eq!(calls, [("a::main".to_owned(), "a::abort".to_owned()), ("a::main".to_owned(), "a::recover".to_owned())]);
+    }
+
+    #[test]
+    fn public_functions_and_uncalled_functions_are_roots() {
+        let mut graph = program(
+            &["a::main", "a::api", "a::unused", "a::helper", "a::recover"],
+            vec![
+                on(ControlPath::Error, call("a::main", "a::api", 1)),
+                call("a::unused", "a::helper", 2),
+                on(ControlPath::Error, call("a::main", "a::recover", 3)),
+            ],
+        );
+        graph.functions[1].public = true;
+        let roots: Vec<&str> = select_roots(&graph, &[Root::Main, Root::Public]).into_iter().collect();
+        assert_eq!(roots, [id("a::api"), id("a::main")]);
+        assert_eq!(functions(&graph), [("a::recover".to_owned(), ControlPath::Error)]);
+    }
+
+    #[test]
+    fn implementations_of_other_crates_traits_are_roots() {
+        let mut graph = program(
+            &["a::main", "a::<a::S as a::Tr>::m", "a::<a::S as core::fmt::Display>::fmt"],
+            vec![
+                on(ControlPath::Error, call("a::main", "a::Tr::m", 1)),
+                on(ControlPath::Error, call("a::main", "a::<a::S as core::fmt::Display>::fmt", 2)),
+            ],
+        );
+        graph.method_decls.push(crate::testing::function("a::Tr::m"));
+        graph.method_impls.push(method_impls("a::Tr::m", &["a::<a::S as a::Tr>::m"]));
+        graph.method_impls.push(method_impls("core::fmt::Display::fmt", &["a::<a::S as core::fmt::Display>::fmt"]));
+        // reached through the declaration of the local trait only
+        assert_eq!(functions(&graph), [("a::<a::S as a::Tr>::m".to_owned(), ControlPath::Error)]);
+    }
+
+    #[test]
+    fn closures_passed_on_run_in_the_function_defining_them() {
+        // `a::recover` passes its closure to a call, `a::make` returns its own
+        let mut graph = program(
+            &["a::main", "a::recover", "a::recover::{closure#0}", "a::make", "a::make::{closure#0}"],
+            vec![
+                on(ControlPath::Error, call("a::main", "a::recover", 1)),
+                call("a::main", "a::make", 2),
+                on(ControlPath::Error, call("a::main", "a::make::{closure#0}", 3)),
+            ],
+        );
+        for (closure, parent, passed_on) in [(2, "a::recover", true), (4, "a::make", false)] {
+            let closure = &mut graph.functions[closure];
+            closure.kind = FunctionKind::Closure;
+            closure.parent = Some(parent.to_owned());
+            closure.parent_id = Some(id(parent));
+            closure.passed_on = passed_on;
+        }
+        assert_eq!(
+            functions(&graph),
+            [
+                ("a::make::{closure#0}".to_owned(), ControlPath::Error),
+                ("a::recover".to_owned(), ControlPath::Error),
+                ("a::recover::{closure#0}".to_owned(), ControlPath::Error),
+            ]
+        );
+    }
+}
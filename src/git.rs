@@ -0,0 +1,225 @@
+//! Impact of the changes in the local git repository, for `callgraph query impact`.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph::CallGraph;
+use crate::impact::Impact;
+
+/// The lines changed in one file, on the side of the working tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangedLines {
+    /// Relative to the root of the repository.
+    pub file: String,
+    /// Inclusive ranges of 1-based lines. Removed lines count as a change of the line
+    /// before them.
+    pub lines: Vec<(usize, usize)>,
+}
+
+/// The functions touched by a diff and their callers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffImpact {
+    /// The revision the working tree is compared to.
+    pub base: String,
+    pub changes: Vec<ChangedLines>,
+    pub impact: Impact,
+    /// The `test:` targets with changed or affected functions, the tests CI should run.
+    pub test_targets: Vec<String>,
+}
+
+impl DiffImpact {
+    /// Runs `git diff` against `base` in the current directory and walks the callers of
+    /// every function whose span overlaps a changed line. Relative span files are resolved
+    /// from the `working_dir` of the crate defining the function, or from the current
+    /// directory for graphs that do not know it.
+    pub fn new(graph: &CallGraph, base: &str, max_depth: Option<usize>) -> Result<DiffImpact, String> {
+        let root = git(&["rev-parse", "--show-toplevel"])?;
+        let root = PathBuf::from(root.trim());
+        let current_dir = std::env::current_dir().map_err(|e| format!("could not read the current directory: {}", e))?;
+        let diff = git(&["diff", "--unified=0", "--no-color", "--no-ext-diff", base, "--"])?;
+        let changes = parse_diff(&diff);
+
+        let mut changed: Vec<&str> = graph
+            .functions
+            .iter()
+            .filter(|function| {
+                let Some(span) = &function.span else {
+                    return false;
+                };
+                let working_dir = match graph.crate_of(function).and_then(|identity| identity.working_dir.as_ref()) {
+                    Some(dir) => Path::new(dir),
+                    None => &current_dir,
+                };
+                changes.iter().any(|change| {
+                    same_file(&span.file, &change.file, working_dir, &root)
+                        && change.lines.iter().any(|(start, end)| span.line <= *end && *start <= span.end_line)
+                })
+            })
+            .map(|function| function.id.as_str())
+            .collect();
+        changed.sort();
+
+        let impact = Impact::new(graph, &changed, max_depth);
+        let test_targets = impact.targets.iter().filter(|target| target.starts_with("test:")).cloned().collect();
+        Ok(DiffImpact { base: base.to_owned(), changes, impact, test_targets })
+    }
+
+    /// Prints the human readable report used by `--format text`.
+    pub fn dump(&self) {
+        println!("Changes since {}:", self.base);
+        for change in &self.changes {
+            let lines: Vec<String> = change
+                .lines
+                .iter()
+                .map(|(start, end)| if start == end { start.to_string() } else { format!("{}-{}", start, end) })
+                .collect();
+            println!("  {}: {}", change.file, lines.join(", "));
+        }
+        println!();
+
+        self.impact.dump();
+
+        println!("\nAffected Test Targets ({}):", self.test_targets.len());
+        for target in &self.test_targets {
+            println!("  {}", target);
+        }
+    }
+}
+
+fn git(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| format!("could not run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Reads the new side of the hunks of a `git diff --unified=0`. File names are only taken
+// from the `---`/`+++` pair of a file header, added lines can start with `++` too.
+fn parse_diff(diff: &str) -> Vec<ChangedLines> {
+    let mut changes: Vec<ChangedLines> = Vec::new();
+    // between `diff --git` and the first hunk of a file
+    let mut in_header = false;
+    let mut after_old_file = false;
+    let mut deleted_file = false;
+    for line in diff.lines() {
+        if line.starts_with("diff --git ") {
+            in_header = true;
+            after_old_file = false;
+            deleted_file = false;
+        } else if in_header {
+            if line.starts_with("--- ") {
+                after_old_file = true;
+            } else if let Some(path) = line.strip_prefix("+++ ").filter(|_| after_old_file) {
+                in_header = false;
+                deleted_file = path == "/dev/null";
+                if !deleted_file {
+                    let file = path.strip_prefix("b/").unwrap_or(path).to_owned();
+                    changes.push(ChangedLines { file, lines: Vec::new() });
+                }
+            } else {
+                after_old_file = false;
+            }
+        } else if let Some(hunk) = line.strip_prefix("@@ ") {
+            if deleted_file {
+                continue;
+            }
+            // @@ -old_start,old_count +new_start,new_count @@
+            let Some(new) = hunk.split_whitespace().find_map(|range| range.strip_prefix('+')) else {
+                continue;
+            };
+            let (start, count) = match new.split_once(',') {
+                Some((start, count)) => (start.parse().unwrap_or(0), count.parse().unwrap_or(0)),
+                None => (new.parse().unwrap_or(0), 1),
+            };
+            let range = if count == 0 { (start, start) } else { (start, start + count - 1) };
+            if let Some(change) = changes.last_mut() {
+                change.lines.push(range);
+            }
+        }
+    }
+    changes.retain(|change| !change.lines.is_empty());
+    changes
+}
+
+// Span files are relative to `working_dir`, where the compiler ran, or absolute; diff files
+// are relative to the repository root.
+fn same_file(span_file: &str, diff_file: &str, working_dir: &Path, root: &Path) -> bool {
+    absolute(working_dir, span_file) == absolute(root, diff_file)
+}
+
+fn absolute(base: &Path, file: &str) -> PathBuf {
+    let path = base.join(file);
+    path.canonicalize().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn added_lines_starting_with_plus() {
+        let diff = "\
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -3,0 +4,2 @@ fn f() {
++++ x
++--- y
+@@ -10 +12 @@ fn g() {
+-    a
++    b
+diff --git a/src/old.rs b/src/old.rs
+deleted file mode 100644
+--- a/src/old.rs
++++ /dev/null
+@@ -1,2 +0,0 @@
+-fn h() {}
+-++ z
+diff --git a/src/new.rs b/src/new.rs
+new file mode 100644
+--- /dev/null
++++ b/src/new.rs
+@@ -0,0 +1 @@
++fn k() {}
+";
+        let changes = parse_diff(diff);
+        let changes: Vec<(&str, &[(usize, usize)])> =
+            changes.iter().map(|change| (change.file.as_str(), change.lines.as_slice())).collect();
+        assert_eq!(changes, [("src/lib.rs", &[(4, 5), (12, 12)][..]), ("src/new.rs", &[(1, 1)][..])]);
+    }
+
+    #[test]
+    fn removed_lines_change_the_line_before() {
+        let diff = "\
+diff --git a/a.rs b/a.rs
+--- a/a.rs
++++ b/a.rs
+@@ -7,2 +6,0 @@
+-    x();
+-    y();
+";
+        assert_eq!(parse_diff(diff)[0].lines, [(6, 6)]);
+    }
+
+    #[test]
+    fn same_file_compares_whole_paths() {
+        let root = Path::new("/work");
+        assert!(same_file("member_a/src/lib.rs", "member_a/src/lib.rs", root, root));
+        assert!(!same_file("member_a/src/lib.rs", "member_b/src/lib.rs", root, root));
+        assert!(!same_file("src/lib.rs", "member_a/src/lib.rs", root, root));
+        assert!(same_file("/work/member_a/src/lib.rs", "member_a/src/lib.rs", Path::new("/elsewhere"), root));
+        // compiled in a member of the workspace
+        assert!(same_file("src/lib.rs", "member_a/src/lib.rs", &root.join("member_a"), root));
+    }
+}
@@ -0,0 +1,659 @@
+//! The call graph model returned by [`crate::analyze`].
+//!
+//! Everything here owns its data: functions are identified by their def path and id
+//! (see the `symbol` module for the naming scheme) instead of compiler `DefId`s, so a
+//! `CallGraph` outlives the compiler session and round-trips through serde.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use rustc_middle::ty::TyCtxt;
+use rustc_span::Span;
+use serde::{Deserialize, Serialize};
+
+/// Version of the JSON schema produced by `--format json`.
+/// Bump this whenever a field is added, removed or changes meaning.
+pub const SCHEMA_VERSION: u32 = 18;
+
+/// The whole call graph of one crate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallGraph {
+    pub schema_version: u32,
+    /// Name of the analyzed crate.
+    #[serde(rename = "crate")]
+    pub krate: String,
+    /// The analyzed crates: the local crate, or every crate of a merged graph.
+    pub crates: Vec<CrateIdentity>,
+    /// Free functions, methods and provided trait methods.
+    pub functions: Vec<Function>,
+    /// Trait method declarations, with or without a default implementation.
+    pub method_decls: Vec<Function>,
+    /// Maps each declaration to the methods implementing it.
+    pub method_impls: Vec<MethodImpls>,
+    pub calls: Vec<Call>,
+}
+
+/// Identifies a crate across compiler sessions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrateIdentity {
+    /// The crate name used by rustc, e.g. `my_crate` for the package `my-crate`. Def paths
+    /// of the crate's functions start with it.
+    pub name: String,
+    /// The stable crate id as hex, which tells apart crates with the same name, e.g. the lib
+    /// and the bin of a package, or its unit tests.
+    pub disambiguator: String,
+    /// The package version, only known when the crate is compiled by Cargo.
+    pub version: Option<String>,
+    /// The Cargo package id, like `path+file:///work/my-crate#0.1.0`. Only known when
+    /// the crate is compiled by Cargo.
+    pub package_id: Option<String>,
+    /// The directory the compiler ran in, relative span files start there. Cargo runs it in
+    /// the workspace root for workspace members.
+    pub working_dir: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Function {
+    pub def_path: String,
+    /// Stable id of the function, the same in every graph naming it.
+    pub id: String,
+    #[serde(rename = "crate")]
+    pub krate: String,
+    /// The enclosing module, the crate name for the crate root.
+    pub module: String,
+    pub span: Option<Location>,
+    /// The Cargo target defining the function, e.g. `lib:url` or `test:parsing`.
+    /// Only known when running through `cargo callgraph`.
+    pub target: Option<String>,
+    pub kind: FunctionKind,
+    /// For closures, the def path of the function or closure defining it.
+    pub parent: Option<String>,
+    /// The id of `parent`.
+    pub parent_id: Option<String>,
+    /// For closures, whether the function defining it passes it to a call, e.g. to
+    /// `Iterator::map`, which may run it. A closure that is only returned or stored runs
+    /// where it is called.
+    pub passed_on: bool,
+    /// Reachable from other crates, i.e. part of the public API of a library.
+    pub public: bool,
+    /// The `main` function of a binary.
+    pub entry: bool,
+    /// Operations of the function that may panic without calling another function.
+    pub panic_sites: Vec<PanicSite>,
+    /// Declared `unsafe fn`.
+    pub unsafe_fn: bool,
+    /// The `unsafe` blocks written in the function, or in the macros of its crate.
+    pub unsafe_blocks: Vec<Location>,
+    /// Def paths of the functions of `extern` blocks it calls.
+    pub foreign_calls: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PanicSite {
+    pub kind: PanicKind,
+    pub span: Option<Location>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PanicKind {
+    /// Indexing out of bounds, e.g. `v[i]`.
+    Index,
+    /// Arithmetic overflow, checked in debug builds. Only found with `--backend mir`.
+    Overflow,
+    /// Division or remainder by zero. Only found with `--backend mir`.
+    DivisionByZero,
+}
+
+impl fmt::Display for PanicKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PanicKind::Index => "index",
+            PanicKind::Overflow => "overflow",
+            PanicKind::DivisionByZero => "division_by_zero",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FunctionKind {
+    /// Free functions, methods and trait methods.
+    Fn,
+    Closure,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MethodImpls {
+    /// Def path of the trait method declaration.
+    pub decl: String,
+    pub decl_id: String,
+    /// Def paths of the implementing methods, including the provided default.
+    pub impls: Vec<String>,
+    /// The ids of `impls`, in the same order.
+    pub impl_ids: Vec<String>,
+}
+
+impl MethodImpls {
+    /// The def paths and ids of the implementations.
+    pub fn implementations(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.impls.iter().zip(&self.impl_ids).map(|(def_path, id)| (def_path.as_str(), id.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallKind {
+    /// The callee is known at compile time.
+    Static,
+    /// The call goes through a trait object; the callee is the trait method declaration.
+    /// Calls through function pointers, `dyn Fn` and `impl Fn` go to the closures and
+    /// functions that may be stored in the called local, or to the `Fn*` trait method.
+    Dynamic,
+    /// The callee is defined in another crate.
+    NonLocal,
+}
+
+impl fmt::Display for CallKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CallKind::Static => "static",
+            CallKind::Dynamic => "dynamic",
+            CallKind::NonLocal => "non_local",
+        })
+    }
+}
+
+impl std::str::FromStr for CallKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "static" => Ok(CallKind::Static),
+            "dynamic" => Ok(CallKind::Dynamic),
+            "non_local" => Ok(CallKind::NonLocal),
+            _ => Err(format!("unknown call kind `{}`, expected `static`, `dynamic` or `non_local`", s)),
+        }
+    }
+}
+
+/// An edge from `caller` to `callee`, with every place where the call happens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Call {
+    pub kind: CallKind,
+    /// Def path of the enclosing function, empty for calls outside of any function.
+    pub caller: String,
+    /// Id of the caller, empty with the caller.
+    pub caller_id: String,
+    pub caller_crate: String,
+    pub callee: String,
+    pub callee_id: String,
+    pub callee_crate: String,
+    pub callee_span: Option<Location>,
+    /// The smallest `constraint_depth` of all sites.
+    pub constraint_depth: usize,
+    /// The largest loop nesting depth of all sites.
+    pub loop_depth: usize,
+    /// Set on the edges from a dynamic call to the implementations it may dispatch to,
+    /// see `--dyn-resolution`. Their sites are those of the dynamic call.
+    pub may_call: bool,
+    /// Number of call sites.
+    pub count: usize,
+    /// The call expressions, in source order.
+    pub sites: Vec<CallSite>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallSite {
+    /// The call expression.
+    pub span: Option<Location>,
+    /// Number of conditions (`if`, `&&`, `match`) guarding this call.
+    pub constraint_depth: usize,
+    /// What must hold for the call to run: the conditions of the enclosing `if`s, `&&`
+    /// and `||` operands, `match` arms and `let ... else`. `None` if the call always runs
+    /// when its function does.
+    pub condition: Option<Condition>,
+    /// `condition` as source text.
+    pub condition_text: Option<String>,
+    /// The loops around the call in its function, outermost first. Their number is the
+    /// loop nesting depth.
+    pub loops: Vec<Loop>,
+    /// Whether the call runs on the normal path, or only when failing.
+    pub path: ControlPath,
+}
+
+/// The control flow path of a call site. Ordered from the most to the least common: a
+/// call on both paths, e.g. in a panic while handling an error, takes the greater one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlPath {
+    #[default]
+    Normal,
+    /// Only runs when returning an error: the residual of `?`, `Err` patterns and
+    /// branches ending in `return Err(..)`.
+    Error,
+    /// Only runs when panicking: `panic!`, `unreachable!`, `todo!`, failing assertions
+    /// and branches ending in a panic.
+    Panic,
+}
+
+impl fmt::Display for ControlPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ControlPath::Normal => "normal",
+            ControlPath::Error => "error",
+            ControlPath::Panic => "panic",
+        })
+    }
+}
+
+/// A loop around a call site.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Loop {
+    pub kind: LoopKind,
+    /// The whole loop expression.
+    pub span: Option<Location>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopKind {
+    Loop,
+    While,
+    For,
+}
+
+impl fmt::Display for LoopKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LoopKind::Loop => "loop",
+            LoopKind::While => "while",
+            LoopKind::For => "for",
+        })
+    }
+}
+
+impl fmt::Display for Loop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.span {
+            Some(span) => write!(f, "{} at {}", self.kind, span),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+/// A boolean formula over the expressions and patterns of the source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Condition {
+    /// A boolean expression, e.g. `x > 0` or `s.is_empty()`.
+    Expr { text: String, span: Option<Location> },
+    /// `scrutinee` matches `pattern`, for `if let`, `let ... else` and `match` arms.
+    Matches { scrutinee: String, pattern: String, span: Option<Location> },
+    Not { operand: Box<Condition> },
+    And { operands: Vec<Condition> },
+    Or { operands: Vec<Condition> },
+}
+
+impl Condition {
+    /// The conjunction of `conditions`, `None` if there are none.
+    pub fn all(conditions: impl IntoIterator<Item = Condition>) -> Option<Condition> {
+        let mut operands = Vec::new();
+        for condition in conditions {
+            match condition {
+                Condition::And { operands: nested } => operands.extend(nested),
+                condition => operands.push(condition),
+            }
+        }
+        match operands.len() {
+            0 => None,
+            1 => operands.pop(),
+            _ => Some(Condition::And { operands }),
+        }
+    }
+}
+
+impl std::ops::Not for Condition {
+    type Output = Condition;
+
+    /// The negation, without double negations.
+    fn not(self) -> Condition {
+        match self {
+            Condition::Not { operand } => *operand,
+            condition => Condition::Not { operand: Box::new(condition) },
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // operands of `&&` and `||` are parenthesized unless they are atoms
+        let operand = |condition: &Condition| match condition {
+            Condition::And { .. } | Condition::Or { .. } => format!("({})", condition),
+            _ => condition.to_string(),
+        };
+        match self {
+            Condition::Expr { text, .. } => write!(f, "{}", text),
+            Condition::Matches { scrutinee, pattern, .. } => write!(f, "let {} = {}", pattern, scrutinee),
+            Condition::Not { operand } => write!(f, "!({})", operand),
+            Condition::And { operands } => {
+                let operands: Vec<String> = operands.iter().map(operand).collect();
+                write!(f, "{}", operands.join(" && "))
+            }
+            Condition::Or { operands } => {
+                let operands: Vec<String> = operands.iter().map(operand).collect();
+                write!(f, "{}", operands.join(" || "))
+            }
+        }
+    }
+}
+
+/// A source range with 1-based lines and columns.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl Location {
+    /// Returns `None` for dummy spans, e.g. the callee span of non local calls.
+    pub(crate) fn from_span(tcx: TyCtxt<'_>, span: Span) -> Option<Location> {
+        if span.is_dummy() {
+            return None;
+        }
+        let source_map = tcx.sess.source_map();
+        let lo = source_map.lookup_char_pos(span.lo());
+        let hi = source_map.lookup_char_pos(span.hi());
+        Some(Location {
+            file: lo.file.name.prefer_local().to_string(),
+            line: lo.line,
+            column: lo.col.0 + 1,
+            end_line: hi.line,
+            end_column: hi.col.0 + 1,
+        })
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}: {}:{}", self.file, self.line, self.column, self.end_line, self.end_column)
+    }
+}
+
+impl CallGraph {
+    /// Calls of the given kind.
+    pub fn calls_of_kind(&self, kind: CallKind) -> impl Iterator<Item = &Call> {
+        self.calls.iter().filter(move |call| call.kind == kind)
+    }
+
+    /// The crate defining `function`, by the crate id in its id.
+    pub fn crate_of(&self, function: &Function) -> Option<&CrateIdentity> {
+        let crate_id = function.id.split(':').nth(1)?;
+        self.crates.iter().find(|identity| identity.disambiguator == crate_id)
+    }
+
+    /// Tags every function and method declaration with `target`.
+    pub fn set_target(&mut self, target: &str) {
+        for function in self.functions.iter_mut().chain(&mut self.method_decls) {
+            function.target = Some(target.to_owned());
+        }
+    }
+
+    /// Links the graphs of several crates into one named `krate`.
+    ///
+    /// A crate can be analyzed more than once, e.g. as a dependency of another member of
+    /// the workspace and on its own, so functions and call sites are only kept once. They
+    /// are matched by id: functions with the same def path in different crates, e.g. the
+    /// lib and the bin of a package, are kept apart.
+    /// Calls into one of the linked crates are resolved by callee id: they get the span of
+    /// the definition, and non local calls become static calls, the callee being part of
+    /// the graph with its own calls.
+    pub fn link(krate: String, graphs: impl IntoIterator<Item = CallGraph>) -> CallGraph {
+        // by def path and id, so that they stay sorted by def path
+        let mut functions: BTreeMap<(String, String), Function> = BTreeMap::new();
+        let mut method_decls: BTreeMap<(String, String), Function> = BTreeMap::new();
+        let mut method_impls: BTreeMap<(String, String), Vec<(String, String)>> = BTreeMap::new();
+        let mut all_calls: Vec<Call> = Vec::new();
+        let mut crates: Vec<CrateIdentity> = Vec::new();
+
+        for graph in graphs {
+            for identity in graph.crates {
+                if !crates.contains(&identity) {
+                    crates.push(identity);
+                }
+            }
+            for function in graph.functions {
+                functions.entry((function.def_path.clone(), function.id.clone())).or_insert(function);
+            }
+            for decl in graph.method_decls {
+                method_decls.entry((decl.def_path.clone(), decl.id.clone())).or_insert(decl);
+            }
+            for decl in graph.method_impls {
+                let impls = method_impls.entry((decl.decl, decl.decl_id)).or_default();
+                for (def_path, id) in decl.impls.into_iter().zip(decl.impl_ids) {
+                    if !impls.iter().any(|(_, known)| *known == id) {
+                        impls.push((def_path, id));
+                    }
+                }
+            }
+            all_calls.extend(graph.calls);
+        }
+
+        // resolve calls into the linked crates to the callee's definition
+        let definitions: HashMap<&str, &Function> = functions
+            .values()
+            .chain(method_decls.values())
+            .map(|function: &Function| (function.id.as_str(), function))
+            .collect();
+        // a may call edge never merges with the call it comes from
+        let mut calls: BTreeMap<(String, String, String, String, CallKind, bool), Call> = BTreeMap::new();
+        for mut call in all_calls {
+            if let Some(callee) = definitions.get(call.callee_id.as_str()) {
+                call.callee.clone_from(&callee.def_path);
+                if callee.span.is_some() {
+                    call.callee_span.clone_from(&callee.span);
+                }
+                if call.kind == CallKind::NonLocal {
+                    call.kind = CallKind::Static;
+                }
+            }
+            let key = (
+                call.caller.clone(),
+                call.caller_id.clone(),
+                call.callee.clone(),
+                call.callee_id.clone(),
+                call.kind,
+                call.may_call,
+            );
+            match calls.get_mut(&key) {
+                Some(existing) => {
+                    for site in call.sites {
+                        if !existing.sites.contains(&site) {
+                            existing.sites.push(site);
+                        }
+                    }
+                    existing.count = existing.sites.len();
+                    existing.constraint_depth = existing.constraint_depth.min(call.constraint_depth);
+                    existing.loop_depth = existing.loop_depth.max(call.loop_depth);
+                }
+                None => {
+                    calls.insert(key, call);
+                }
+            }
+        }
+
+        CallGraph {
+            schema_version: SCHEMA_VERSION,
+            krate,
+            crates,
+            functions: functions.into_values().collect(),
+            method_decls: method_decls.into_values().collect(),
+            method_impls: method_impls
+                .into_iter()
+                .map(|((decl, decl_id), impls)| {
+                    let (impls, impl_ids) = impls.into_iter().unzip();
+                    MethodImpls { decl, decl_id, impls, impl_ids }
+                })
+                .collect(),
+            calls: calls.into_values().collect(),
+        }
+    }
+
+    /// Prints the human readable listing used by `--format text`.
+    pub fn dump(&self) {
+        println!("Functions:");
+        for function in &self.functions {
+            print!("  Function: {}", function.def_path);
+            if let Some(span) = &function.span {
+                print!(", Span: {}", span);
+            }
+            if let Some(target) = &function.target {
+                print!(", Target: {}", target);
+            }
+            println!();
+        }
+
+        println!("\nMethod Declarations:");
+        for decl in &self.method_decls {
+            println!("  Method Declaration: {}", decl.def_path);
+        }
+
+        println!("\nMethod Implementations:");
+        for method_impls in &self.method_impls {
+            println!("  Method Implementation for {}: {:?}", method_impls.decl, method_impls.impls);
+        }
+
+        for (title, kind) in [
+            ("Static Calls", CallKind::Static),
+            ("Dynamic Calls", CallKind::Dynamic),
+            ("Non Local Calls", CallKind::NonLocal),
+        ] {
+            println!("\n{}:", title);
+            for call in self.calls_of_kind(kind) {
+                println!(
+                    "{} --- {} (Constraint Depth: {}, {}Call Count: {}{})",
+                    call.caller,
+                    call.callee,
+                    call.constraint_depth,
+                    if call.loop_depth > 0 { format!("Loop Depth: {}, ", call.loop_depth) } else { String::new() },
+                    call.count,
+                    if call.may_call { ", May Call" } else { "" }
+                );
+                for site in &call.sites {
+                    if let Some(span) = &site.span {
+                        let mut details = format!("Constraint Depth: {}", site.constraint_depth);
+                        if site.path != ControlPath::Normal {
+                            details += &format!(", Path: {}", site.path);
+                        }
+                        if let Some(innermost) = site.loops.last() {
+                            details += &format!(", Loop Depth: {}, Innermost Loop: {}", site.loops.len(), innermost);
+                        }
+                        if let Some(condition) = &site.condition_text {
+                            details += &format!(", Condition: {}", condition);
+                        }
+                        println!("    at {} ({})", span, details);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{call, function, graph, location};
+
+    #[test]
+    fn link_resolves_calls_into_linked_crates() {
+        let lib = {
+            let mut graph = graph(&["a::parse"], Vec::new());
+            graph.functions[0].span = Some(location(3));
+            graph
+        };
+        let mut bin = graph(&["b::main"], Vec::new());
+        let mut call = call("b::main", "a::parse", 1);
+        call.kind = CallKind::NonLocal;
+        bin.calls.push(call);
+
+        let linked = CallGraph::link("ws".to_owned(), [lib, bin]);
+        assert_eq!(linked.functions.len(), 2);
+        assert_eq!(linked.calls.len(), 1);
+        assert_eq!(linked.calls[0].kind, CallKind::Static);
+        assert_eq!(linked.calls[0].callee_span, Some(location(3)));
+    }
+
+    #[test]
+    fn link_keeps_crates_analyzed_twice_once() {
+        let graph = || graph(&["a::f", "a::g"], vec![call("a::f", "a::g", 1), call("a::f", "a::g", 2)]);
+        let linked = CallGraph::link("ws".to_owned(), [graph(), graph()]);
+        assert_eq!(linked.functions.len(), 2);
+        assert_eq!(linked.calls.len(), 1);
+        assert_eq!(linked.calls[0].count, 2);
+    }
+
+    #[test]
+    fn link_keeps_same_def_paths_of_different_crates_apart() {
+        // the lib and the bin of a package
+        let mut bin_fn = function("a::run");
+        bin_fn.id = "a:bin".to_owned();
+        let mut bin_call = call("a::main", "a::run", 1);
+        bin_call.callee_id = bin_fn.id.clone();
+        let mut bin = graph(&["a::main"], vec![bin_call]);
+        bin.functions.push(bin_fn);
+        let lib = graph(&["a::run"], Vec::new());
+
+        let linked = CallGraph::link("ws".to_owned(), [lib, bin]);
+        let runs: Vec<&str> =
+            linked.functions.iter().filter(|function| function.def_path == "a::run").map(|function| function.id.as_str()).collect();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(linked.calls[0].callee_id, "a:bin");
+    }
+
+    #[test]
+    fn analyses_keep_linked_functions_with_the_same_def_path_apart() {
+        // two versions of `util`, `f` calls `g` in the first and `g` calls `f` in the second
+        let version = |id: &str, call: Call| {
+            let mut graph = graph(&["util::f", "util::g"], vec![call]);
+            for function in &mut graph.functions {
+                function.id = format!("{}:{}", id, function.def_path);
+            }
+            graph.calls[0].caller_id = format!("{}:{}", id, graph.calls[0].caller);
+            graph.calls[0].callee_id = format!("{}:{}", id, graph.calls[0].callee);
+            graph
+        };
+        let v1 = version("util-0.1", call("util::f", "util::g", 1));
+        let v2 = version("util-0.2", call("util::g", "util::f", 1));
+        let mut app = graph(&["app::main"], vec![call("app::main", "util::f", 1)]);
+        app.calls[0].callee_id = "util-0.1:util::f".to_owned();
+
+        let linked = CallGraph::link("ws".to_owned(), [app, v1, v2]);
+        assert_eq!(linked.functions.len(), 5);
+        let index = crate::index::CallIndex::new(&linked);
+        assert_eq!(index.successors("util-0.1:util::f").collect::<Vec<_>>(), ["util-0.1:util::g"]);
+        assert_eq!(index.successors("util-0.2:util::f").count(), 0);
+
+        assert!(crate::scc::Recursion::new(&linked).cycles.is_empty());
+        let reachability = crate::reach::Reachability::new(&linked, &[crate::config::Root::Path("app::main".to_owned())]);
+        assert_eq!(reachability.reachable, ["app::main", "util::f", "util::g"]);
+        assert_eq!(reachability.unreachable, ["util::f", "util::g"]);
+        let metrics = crate::metrics::Metrics::new(&linked, &[]);
+        let fan_in: Vec<(&str, usize)> =
+            metrics.functions.iter().map(|function| (function.def_path.as_str(), function.fan_in)).collect();
+        assert_eq!(fan_in, [("app::main", 0), ("util::f", 1), ("util::f", 1), ("util::g", 1), ("util::g", 0)]);
+    }
+
+    #[test]
+    fn link_keeps_may_call_edges_apart() {
+        let mut may_call = call("a::f", "a::g", 2);
+        may_call.may_call = true;
+        let linked = CallGraph::link("ws".to_owned(), [graph(&["a::f", "a::g"], vec![call("a::f", "a::g", 1), may_call])]);
+        assert_eq!(linked.calls.len(), 2);
+        let certain = linked.calls.iter().find(|call| !call.may_call).unwrap();
+        assert_eq!(certain.sites.len(), 1);
+        assert_eq!(certain.sites[0].span, Some(location(1)));
+    }
+}
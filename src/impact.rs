@@ -0,0 +1,131 @@
+//! Impact analysis: the functions that may be affected by a change, walking the
+//! callers transitively.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{CallGraph, Function};
+use crate::index::CallIndex;
+
+/// The functions calling the changed functions, directly or not.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Impact {
+    pub changed: Vec<String>,
+    /// Every function reaching a changed function, closest first.
+    pub affected: Vec<Affected>,
+    /// Affected functions of the public API of libraries, including changed ones.
+    pub public: Vec<String>,
+    /// Affected `main` functions of binaries, including changed ones.
+    pub entry_points: Vec<String>,
+    /// The Cargo targets with affected or changed functions, e.g. `bin:cli`.
+    pub targets: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Affected {
+    pub def_path: String,
+    /// Steps to the closest changed function, through calls, trait method declarations
+    /// and closures.
+    pub distance: usize,
+    /// The next function on the way to that changed function.
+    pub via: String,
+}
+
+impl Impact {
+    /// Walks the callers of the functions with the ids `changed` up to `max_depth` calls
+    /// away, all of them if `None`.
+    ///
+    /// Callers of a trait method declaration are affected by changes of its
+    /// implementations, and a function is affected by changes of the closures it defines.
+    pub fn new(graph: &CallGraph, changed: &[&str], max_depth: Option<usize>) -> Impact {
+        let index = CallIndex::new(graph);
+
+        let mut seen: BTreeMap<&str, (usize, &str)> = BTreeMap::new();
+        let mut queue: VecDeque<(&str, usize)> = changed.iter().map(|id| (*id, 0)).collect();
+        while let Some((id, distance)) = queue.pop_front() {
+            if max_depth.is_some_and(|max_depth| distance >= max_depth) {
+                continue;
+            }
+            for caller in index.predecessors(id) {
+                if changed.contains(&caller) || seen.contains_key(caller) {
+                    continue;
+                }
+                seen.insert(caller, (distance + 1, id));
+                queue.push_back((caller, distance + 1));
+            }
+        }
+
+        // declarations without a default body only link callers to implementations
+        let functions: HashMap<&str, &Function> =
+            graph.functions.iter().map(|function| (function.id.as_str(), function)).collect();
+        let affected: Vec<(&str, &str, usize, &str)> = seen
+            .into_iter()
+            .filter(|(id, _)| functions.contains_key(id) || index.function(id).is_none())
+            .map(|(id, (distance, via))| (id, index.def_path(id), distance, index.def_path(via)))
+            .collect();
+
+        let mut public = BTreeSet::new();
+        let mut entry_points = BTreeSet::new();
+        let mut targets = BTreeSet::new();
+        let all = changed.iter().copied().chain(affected.iter().map(|(id, ..)| *id));
+        for id in all {
+            let Some(function) = functions.get(id) else {
+                continue;
+            };
+            if function.public {
+                public.insert(function.def_path.clone());
+            }
+            if function.entry {
+                entry_points.insert(function.def_path.clone());
+            }
+            if let Some(target) = &function.target {
+                targets.insert(target.clone());
+            }
+        }
+
+        let mut affected: Vec<Affected> = affected
+            .into_iter()
+            .map(|(_, def_path, distance, via)| Affected { def_path: def_path.to_owned(), distance, via: via.to_owned() })
+            .collect();
+        affected.sort_by(|a, b| (a.distance, &a.def_path).cmp(&(b.distance, &b.def_path)));
+        let mut changed: Vec<String> = changed.iter().map(|id| index.def_path(id).to_owned()).collect();
+        changed.sort();
+
+        Impact {
+            changed,
+            affected,
+            public: public.into_iter().collect(),
+            entry_points: entry_points.into_iter().collect(),
+            targets: targets.into_iter().collect(),
+        }
+    }
+
+    /// Prints the human readable report used by `--format text`.
+    pub fn dump(&self) {
+        println!("Changed Functions:");
+        for def_path in &self.changed {
+            println!("  {}", def_path);
+        }
+
+        println!("\nAffected Functions ({}):", self.affected.len());
+        for affected in &self.affected {
+            println!("  {} (Distance: {}, Via: {})", affected.def_path, affected.distance, affected.via);
+        }
+
+        println!("\nAffected Public API ({}):", self.public.len());
+        for def_path in &self.public {
+            println!("  {}", def_path);
+        }
+
+        println!("\nAffected Entry Points ({}):", self.entry_points.len());
+        for def_path in &self.entry_points {
+            println!("  {}", def_path);
+        }
+
+        println!("\nAffected Targets ({}):", self.targets.len());
+        for target in &self.targets {
+            println!("  {}", target);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! Adjacency lists over a [`CallGraph`], shared by the analyses working on whole graphs.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{Call, CallGraph, Function, Location};
+
+/// Calls from and to every function of a graph, by id.
+///
+/// Besides the calls, a function also leads to every closure it defines, and a trait
+/// method declaration leads to its implementations. Unlike [`hops`], closures are linked
+/// whether they are passed on or not: a closure that is returned or stored may be called
+/// through a `dyn Fn` the graph cannot resolve, and reachability and impact analysis
+/// should rather count a function too many than report a live closure as dead.
+pub struct CallIndex<'a> {
+    pub graph: &'a CallGraph,
+    /// Functions and method declarations of the graph.
+    functions: HashMap<&'a str, &'a Function>,
+    /// The def paths of every function the graph names, including those of other crates.
+    def_paths: HashMap<&'a str, &'a str>,
+    calls_from: HashMap<&'a str, Vec<&'a Call>>,
+    calls_to: HashMap<&'a str, Vec<&'a Call>>,
+    successors: HashMap<&'a str, BTreeSet<&'a str>>,
+    predecessors: HashMap<&'a str, BTreeSet<&'a str>>,
+}
+
+impl<'a> CallIndex<'a> {
+    pub fn new(graph: &'a CallGraph) -> CallIndex<'a> {
+        let mut index = CallIndex {
+            graph,
+            functions: HashMap::new(),
+            def_paths: HashMap::new(),
+            calls_from: HashMap::new(),
+            calls_to: HashMap::new(),
+            successors: HashMap::new(),
+            predecessors: HashMap::new(),
+        };
+        for function in graph.functions.iter().chain(&graph.method_decls) {
+            index.functions.entry(&function.id).or_insert(function);
+            index.def_paths.insert(&function.id, &function.def_path);
+        }
+        for call in &graph.calls {
+            // calls outside of any function, e.g. in constants, have no caller
+            if call.caller.is_empty() {
+                continue;
+            }
+            index.def_paths.entry(&call.caller_id).or_insert(&call.caller);
+            index.def_paths.entry(&call.callee_id).or_insert(&call.callee);
+            index.calls_from.entry(&call.caller_id).or_default().push(call);
+            index.calls_to.entry(&call.callee_id).or_default().push(call);
+            index.add_edge(&call.caller_id, &call.callee_id);
+        }
+        for function in &graph.functions {
+            if let Some(parent) = &function.parent_id {
+                index.add_edge(parent, &function.id);
+            }
+        }
+        for method_impls in &graph.method_impls {
+            index.def_paths.entry(&method_impls.decl_id).or_insert(&method_impls.decl);
+            for (def_path, id) in method_impls.implementations() {
+                index.def_paths.entry(id).or_insert(def_path);
+                if id != method_impls.decl_id {
+                    index.add_edge(&method_impls.decl_id, id);
+                }
+            }
+        }
+        index
+    }
+
+    fn add_edge(&mut self, from: &'a str, to: &'a str) {
+        self.successors.entry(from).or_default().insert(to);
+        self.predecessors.entry(to).or_default().insert(from);
+    }
+
+    /// The function or method declaration with this id, `None` for functions of crates
+    /// that are not part of the graph.
+    pub fn function(&self, id: &str) -> Option<&'a Function> {
+        self.functions.get(id).copied()
+    }
+
+    /// The def path of the function with this id, the id itself if the graph does not
+    /// name it.
+    pub fn def_path<'s>(&self, id: &'s str) -> &'s str
+    where
+        'a: 's,
+    {
+        self.def_paths.get(id).copied().unwrap_or(id)
+    }
+
+    pub fn calls_from(&self, id: &str) -> &[&'a Call] {
+        self.calls_from.get(id).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn calls_to(&self, id: &str) -> &[&'a Call] {
+        self.calls_to.get(id).map_or(&[], Vec::as_slice)
+    }
+
+    /// The ids of the functions called by, defined in or implementing `id`, in id order.
+    pub fn successors(&self, id: &str) -> impl Iterator<Item = &'a str> + '_ {
+        self.successors.get(id).into_iter().flatten().copied()
+    }
+
+    /// The reverse of [`CallIndex::successors`].
+    pub fn predecessors(&self, id: &str) -> impl Iterator<Item = &'a str> + '_ {
+        self.predecessors.get(id).into_iter().flatten().copied()
+    }
+}
+
+/// One step of a call chain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hop {
+    pub caller: String,
+    pub caller_id: String,
+    pub callee: String,
+    pub callee_id: String,
+    /// The call kind, `impl` from a trait method declaration to an implementation, or
+    /// `closure` from a function to a closure it defines and passes on.
+    pub kind: String,
+    /// The first call site.
+    pub span: Option<Location>,
+}
+
+/// The calls of `graph`, and the links from trait method declarations to their
+/// implementations and from functions to the closures they define and pass to a call.
+/// A closure that is only returned or stored is reached through the calls to it.
+pub fn hops(graph: &CallGraph) -> Vec<Hop> {
+    let link = |from: (&str, &str), to: (&str, &str), kind: &str| Hop {
+        caller: from.0.to_owned(),
+        caller_id: from.1.to_owned(),
+        callee: to.0.to_owned(),
+        callee_id: to.1.to_owned(),
+        kind: kind.to_owned(),
+        span: None,
+    };
+    let mut hops: Vec<Hop> = graph
+        .calls
+        .iter()
+        .filter(|call| !call.caller.is_empty())
+        .map(|call| Hop {
+            caller: call.caller.clone(),
+            caller_id: call.caller_id.clone(),
+            callee: call.callee.clone(),
+            callee_id: call.callee_id.clone(),
+            kind: call.kind.to_string(),
+            span: call.sites.first().and_then(|site| site.span.clone()),
+        })
+        .collect();
+    for method_impls in &graph.method_impls {
+        let decl = (method_impls.decl.as_str(), method_impls.decl_id.as_str());
+        for (def_path, id) in method_impls.implementations() {
+            if id != method_impls.decl_id {
+                hops.push(link(decl, (def_path, id), "impl"));
+            }
+        }
+    }
+    for function in &graph.functions {
+        if let (Some(parent), Some(parent_id), true) = (&function.parent, &function.parent_id, function.passed_on) {
+            hops.push(link((parent, parent_id), (&function.def_path, &function.id), "closure"));
+        }
+    }
+    hops
+}
+
+/// The shortest chains of `hops` to the `targets`, breadth first. A target is the id of a
+/// function with the first hop of its chain, `None` for the functions that are the end of a
+/// chain themselves. Earlier targets are preferred. Returns the first hop of the chain of
+/// every function reaching a target, by id.
+pub fn shortest_chains<'a>(
+    hops: &'a [Hop],
+    targets: impl IntoIterator<Item = (&'a str, Option<&'a Hop>)>,
+) -> HashMap<&'a str, Option<&'a Hop>> {
+    let mut callers: HashMap<&str, Vec<&Hop>> = HashMap::new();
+    for hop in hops {
+        callers.entry(&hop.callee_id).or_default().push(hop);
+    }
+    let mut first_hops: HashMap<&str, Option<&Hop>> = HashMap::new();
+    let mut queue: VecDeque<&str> = VecDeque::new();
+    for (id, hop) in targets {
+        if !first_hops.contains_key(id) {
+            first_hops.insert(id, hop);
+            queue.push_back(id);
+        }
+    }
+    while let Some(id) = queue.pop_front() {
+        for hop in callers.get(id).into_iter().flatten() {
+            if !first_hops.contains_key(hop.caller_id.as_str()) {
+                first_hops.insert(&hop.caller_id, Some(*hop));
+                queue.push_back(&hop.caller_id);
+            }
+        }
+    }
+    first_hops
+}
+
+/// The chain of the function `id` in the result of [`shortest_chains`], and the id of the
+/// function it ends at.
+pub fn chain<'a>(first_hops: &HashMap<&str, Option<&'a Hop>>, id: &'a str) -> (Vec<Hop>, &'a str) {
+    let mut hops = Vec::new();
+    let mut current = id;
+    while let Some(Some(hop)) = first_hops.get(current) {
+        hops.push((*hop).clone());
+        current = &hop.callee_id;
+    }
+    (hops, current)
+}
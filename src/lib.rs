@@ -1,23 +1,55 @@
+//! Computes the call graph of a crate from inside the compiler.
+//!
+//! The `callgraph` and `cargo-callgraph` binaries are thin drivers around [`analyze`].
+//! Custom rustc drivers can call it from their own `after_analysis` callback and work
+//! on the returned [`CallGraph`], which owns all its data and can be serialized with serde.
 #![feature(rustc_private)]
 
 extern crate rustc_driver;
 extern crate rustc_interface;
 extern crate rustc_hir;
+extern crate rustc_hir_pretty;
 extern crate rustc_middle;
 extern crate rustc_span;
 extern crate rustc_version;
 extern crate cargo_metadata;
 
+use std::path::{Path, PathBuf};
+
+use rustc_hir::def_id::LOCAL_CRATE;
 use rustc_middle::ty::TyCtxt;
 
+mod config;
+pub mod diff;
+pub mod dot;
+pub mod failure;
+pub mod git;
+pub mod graph;
+pub mod impact;
+pub mod index;
+pub mod link;
+pub mod metrics;
+mod mir;
+pub mod panics;
+pub mod query;
+pub mod reach;
+pub mod scc;
+mod symbol;
+#[cfg(test)]
+mod testing;
+pub mod unsafety;
 mod visitor;
 
+pub use config::{Backend, Cluster, Config, DynResolution, OutputFormat, Root};
+pub use graph::CallGraph;
+
 //项目的模块结构
 //主要的公共API
 //各个组件是如何组织在一起的
 
 /// Returns the "default sysroot" that Callgraph will use if no `--sysroot` flag is set.
 /// Should be a compile-time constant.
+#[allow(clippy::option_env_unwrap)]
 pub fn compile_time_sysroot() -> Option<String> {
     // option_env! is replaced to a constant at compile time
     if option_env!("RUSTC_STAGE").is_some() {
@@ -40,9 +72,99 @@ pub fn compile_time_sysroot() -> Option<String> {
     })
 }
 
-pub fn analyze(&tcx: &TyCtxt<'_>) {
-    let mut visitor = visitor::CallgraphVisitor::new(&tcx);
+/// Computes the call graph of the local crate. Must be called after the analysis phase.
+pub fn analyze(tcx: TyCtxt<'_>) -> CallGraph {
+    analyze_with(tcx, &Config::default())
+}
+
+/// Like [`analyze`], with the analysis options of `config`.
+pub fn analyze_with(tcx: TyCtxt<'_>, config: &Config) -> CallGraph {
+    let mut visitor = visitor::CallgraphVisitor::new(tcx, config.dyn_resolution);
     tcx.hir().visit_all_item_likes_in_crate(&mut visitor);
-    
-    visitor.dump();
+    if config.backend == Backend::Mir {
+        mir::collect_calls(tcx, &mut visitor);
+    }
+    visitor.graph()
+}
+
+/// Prints `graph` to stdout in the format selected by `config`, or the report
+/// asked for instead of the graph.
+pub fn emit(graph: &CallGraph, config: &Config) {
+    if config.metrics {
+        let metrics = metrics::Metrics::new(graph, &config.roots);
+        match config.format {
+            OutputFormat::Json => print_json(&metrics),
+            OutputFormat::Csv => print!("{}", metrics.to_csv()),
+            _ => metrics.dump(),
+        }
+        return;
+    }
+    if !config.roots.is_empty() {
+        let reachability = reach::Reachability::new(graph, &config.roots);
+        match config.format {
+            OutputFormat::Json => print_json(&reachability),
+            _ => reachability.dump(graph),
+        }
+        return;
+    }
+    if config.failure_paths {
+        let failure = failure::FailurePaths::new(graph);
+        match config.format {
+            OutputFormat::Json => print_json(&failure),
+            _ => failure.dump(),
+        }
+        return;
+    }
+    if config.panics {
+        let panics = panics::Panics::new(graph);
+        match config.format {
+            OutputFormat::Json => print_json(&panics),
+            _ => panics.dump(),
+        }
+        return;
+    }
+    if config.unsafe_reach {
+        let unsafety = unsafety::Unsafety::new(graph);
+        match config.format {
+            OutputFormat::Json => print_json(&unsafety),
+            _ => unsafety.dump(),
+        }
+        return;
+    }
+    if config.recursion {
+        let recursion = scc::Recursion::new(graph);
+        match config.format {
+            OutputFormat::Json => print_json(&recursion),
+            _ => recursion.dump(),
+        }
+        return;
+    }
+
+    match config.format {
+        // CSV is only supported for `--metrics`, see `Config::from_args`
+        OutputFormat::Text | OutputFormat::Csv => graph.dump(),
+        OutputFormat::Json => print_json(graph),
+        OutputFormat::Dot => print!("{}", dot::render(graph, config.cluster)),
+    }
+}
+
+pub(crate) fn print_json<T: serde::Serialize>(value: &T) {
+    let stdout = std::io::stdout().lock();
+    serde_json::to_writer_pretty(stdout, value).expect("failed to write JSON output");
+    println!();
+}
+
+/// Saves `graph` as JSON into `dir` and returns the path of the new file.
+/// The file name is unique per crate, so the graphs of a lib and a bin with the same name
+/// do not overwrite each other, while analyzing the same crate again does.
+pub fn save(tcx: TyCtxt<'_>, graph: &CallGraph, dir: &Path) -> std::io::Result<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(format!(
+        "{}-{:016x}.json",
+        tcx.crate_name(LOCAL_CRATE),
+        tcx.stable_crate_id(LOCAL_CRATE).as_u64()
+    ));
+    let file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+    serde_json::to_writer(file, graph)?;
+    Ok(path)
 }
@@ -0,0 +1,57 @@
+//! `callgraph link`: stitches the graphs of several crates saved with `--format json` or
+//! `--save-dir` into one, see [`CallGraph::link`].
+
+use std::path::{Path, PathBuf};
+
+use crate::config::{take_flag_value, Config};
+use crate::emit;
+use crate::graph::CallGraph;
+use crate::query::load_graph;
+
+/// Runs `callgraph link <graph.json | dir>... [--name name] [options]`. Directories are
+/// read like `--save-dir` writes them; the options are those of `callgraph`, e.g. `--format`.
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    let name = take_flag_value(&mut args, "--name")?.unwrap_or_else(|| "linked".to_owned());
+    let config = Config::from_args(&mut args)?;
+    let mut paths = Vec::new();
+    for arg in &args {
+        if arg.starts_with('-') {
+            return Err(format!("unexpected argument `{}`", arg));
+        }
+        let path = PathBuf::from(arg);
+        if path.is_dir() {
+            paths.extend(saved_graphs(&path)?);
+        } else {
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        return Err("expected call graphs: `callgraph link <graph.json | dir>...`".to_owned());
+    }
+    let graphs = paths
+        .iter()
+        .map(|path| load_graph(Some(&path.display().to_string())))
+        .collect::<Result<Vec<_>, _>>()?;
+    emit(&CallGraph::link(name, graphs), &config);
+    Ok(())
+}
+
+/// Links the graphs saved by `callgraph --save-dir` into `dir`.
+pub fn link_saved(name: String, dir: &Path) -> Result<CallGraph, String> {
+    let graphs = saved_graphs(dir)?
+        .iter()
+        .map(|path| load_graph(Some(&path.display().to_string())))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(CallGraph::link(name, graphs))
+}
+
+// The JSON files of `dir`, in a stable order.
+fn saved_graphs(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("could not read {}: {}", dir.display(), e))?;
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
@@ -0,0 +1,431 @@
+//! Per function metrics for `--metrics`, to find the hotspots of a code base.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt::Write;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Root;
+use crate::graph::CallGraph;
+use crate::index::CallIndex;
+use crate::reach::select_roots;
+use crate::scc::tarjan;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Metrics {
+    pub functions: Vec<FunctionMetrics>,
+    pub modules: Vec<GroupMetrics>,
+    pub crates: Vec<GroupMetrics>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionMetrics {
+    pub def_path: String,
+    #[serde(rename = "crate")]
+    pub krate: String,
+    pub module: String,
+    /// Number of distinct callers.
+    pub fan_in: usize,
+    /// Number of distinct callees, including functions of other crates.
+    pub fan_out: usize,
+    /// The longest chain of calls from a root, `None` if no root reaches the function.
+    /// Recursive functions count once.
+    pub depth: Option<usize>,
+    /// Betweenness centrality: the number of shortest call chains between two other
+    /// functions passing through this one, split among chains of the same length.
+    pub betweenness: f64,
+}
+
+/// Metrics of the functions of a module or crate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GroupMetrics {
+    pub name: String,
+    pub functions: usize,
+    /// Calls between functions of the group.
+    pub internal_calls: usize,
+    /// Calls from functions outside of the group.
+    pub incoming_calls: usize,
+    /// Calls to functions outside of the group.
+    pub outgoing_calls: usize,
+    pub max_fan_in: usize,
+    pub max_fan_out: usize,
+    pub max_depth: Option<usize>,
+    pub max_betweenness: f64,
+}
+
+impl Metrics {
+    /// Depths start at `roots`; without roots at the `main` functions and the public API,
+    /// or at the functions nobody calls if there are neither.
+    pub fn new(graph: &CallGraph, roots: &[Root]) -> Metrics {
+        let index = CallIndex::new(graph);
+
+        // the functions of the graph, with method declarations linking to their implementations
+        let names: BTreeSet<(&str, &str)> = graph
+            .functions
+            .iter()
+            .chain(&graph.method_decls)
+            .map(|function| (function.def_path.as_str(), function.id.as_str()))
+            .collect();
+        let names: Vec<&str> = names.into_iter().map(|(_, id)| id).collect();
+        let nodes: HashMap<&str, usize> = names.iter().enumerate().map(|(i, name)| (*name, i)).collect();
+        let adjacency: Vec<Vec<usize>> = names
+            .iter()
+            .map(|name| index.successors(name).filter_map(|next| nodes.get(next).copied()).collect())
+            .collect();
+
+        let mut selected = if roots.is_empty() {
+            select_roots(graph, &[Root::Main, Root::Public])
+        } else {
+            select_roots(graph, roots)
+        };
+        if selected.is_empty() && roots.is_empty() {
+            selected = names
+                .iter()
+                .copied()
+                .filter(|name| index.predecessors(name).next().is_none())
+                .collect();
+        }
+        let depths = depths(&adjacency, selected.iter().filter_map(|root| nodes.get(root).copied()));
+        let betweenness = betweenness(&adjacency);
+
+        let mut functions: Vec<FunctionMetrics> = graph
+            .functions
+            .iter()
+            .map(|function| {
+                let id = function.id.as_str();
+                let callers: BTreeSet<&str> = index.calls_to(id).iter().map(|call| call.caller_id.as_str()).collect();
+                let callees: BTreeSet<&str> = index.calls_from(id).iter().map(|call| call.callee_id.as_str()).collect();
+                FunctionMetrics {
+                    def_path: function.def_path.clone(),
+                    krate: function.krate.clone(),
+                    module: function.module.clone(),
+                    fan_in: callers.len(),
+                    fan_out: callees.len(),
+                    depth: depths[nodes[id]],
+                    betweenness: betweenness[nodes[id]],
+                }
+            })
+            .collect();
+        functions.sort_by(|a, b| a.def_path.cmp(&b.def_path));
+
+        let modules = group(graph, &functions, |function| function.module.as_str());
+        let crates = group(graph, &functions, |function| function.krate.as_str());
+        Metrics { functions, modules, crates }
+    }
+
+    /// Prints the tables used by `--format text`, hotspots first.
+    pub fn dump(&self) {
+        let mut functions: Vec<&FunctionMetrics> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.betweenness.total_cmp(&a.betweenness).then_with(|| a.def_path.cmp(&b.def_path)));
+        let rows: Vec<[String; 5]> = functions
+            .iter()
+            .map(|function| {
+                [
+                    function.def_path.clone(),
+                    function.fan_in.to_string(),
+                    function.fan_out.to_string(),
+                    depth(function.depth),
+                    format!("{:.2}", function.betweenness),
+                ]
+            })
+            .collect();
+        println!("Functions:");
+        print_table(&["Function", "Fan In", "Fan Out", "Depth", "Betweenness"], &rows);
+
+        for (title, groups) in [("Modules", &self.modules), ("Crates", &self.crates)] {
+            let rows: Vec<[String; 9]> = groups
+                .iter()
+                .map(|group| {
+                    [
+                        group.name.clone(),
+                        group.functions.to_string(),
+                        group.internal_calls.to_string(),
+                        group.incoming_calls.to_string(),
+                        group.outgoing_calls.to_string(),
+                        group.max_fan_in.to_string(),
+                        group.max_fan_out.to_string(),
+                        depth(group.max_depth),
+                        format!("{:.2}", group.max_betweenness),
+                    ]
+                })
+                .collect();
+            println!("\n{}:", title);
+            print_table(
+                &[
+                    &title[..title.len() - 1],
+                    "Functions",
+                    "Internal Calls",
+                    "Incoming Calls",
+                    "Outgoing Calls",
+                    "Max Fan In",
+                    "Max Fan Out",
+                    "Max Depth",
+                    "Max Betweenness",
+                ],
+                &rows,
+            );
+        }
+    }
+
+    /// One CSV table for all levels: the `level` column is `function`, `module` or
+    /// `crate`. For modules and crates, `fan_in` and `fan_out` are the incoming and
+    /// outgoing calls, and `depth` and `betweenness` the maximum of their functions.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "level,name,crate,functions,internal_calls,fan_in,fan_out,depth,betweenness").unwrap();
+        for function in &self.functions {
+            writeln!(
+                out,
+                "function,{},{},1,,{},{},{},{:.4}",
+                csv_field(&function.def_path),
+                csv_field(&function.krate),
+                function.fan_in,
+                function.fan_out,
+                function.depth.map_or(String::new(), |depth| depth.to_string()),
+                function.betweenness
+            )
+            .unwrap();
+        }
+        for (level, groups) in [("module", &self.modules), ("crate", &self.crates)] {
+            for group in groups {
+                let krate = group.name.split("::").next().unwrap_or_default();
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{},{},{},{:.4}",
+                    level,
+                    csv_field(&group.name),
+                    csv_field(krate),
+                    group.functions,
+                    group.internal_calls,
+                    group.incoming_calls,
+                    group.outgoing_calls,
+                    group.max_depth.map_or(String::new(), |depth| depth.to_string()),
+                    group.max_betweenness
+                )
+                .unwrap();
+            }
+        }
+        out
+    }
+}
+
+fn depth(depth: Option<usize>) -> String {
+    depth.map_or("-".to_owned(), |depth| depth.to_string())
+}
+
+fn print_table<const N: usize>(header: &[&str; N], rows: &[[String; N]]) {
+    let mut widths: Vec<usize> = header.iter().map(|title| title.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    let line = |cells: Vec<&str>| {
+        let cells: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            // names left aligned, numbers right aligned
+            .map(|(i, (cell, width))| if i == 0 { format!("{:<width$}", cell) } else { format!("{:>width$}", cell) })
+            .collect();
+        println!("  {}", cells.join("  ").trim_end());
+    };
+    line(header.to_vec());
+    for row in rows {
+        line(row.iter().map(String::as_str).collect());
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+// Aggregates the functions by the group `key` returns.
+fn group<'a>(graph: &'a CallGraph, functions: &'a [FunctionMetrics], key: impl Fn(&'a FunctionMetrics) -> &'a str) -> Vec<GroupMetrics> {
+    let group_of: HashMap<&str, &str> = functions.iter().map(|function| (function.def_path.as_str(), key(function))).collect();
+    let mut groups: BTreeMap<&str, GroupMetrics> = BTreeMap::new();
+    for function in functions {
+        let group = groups.entry(key(function)).or_insert_with(|| GroupMetrics {
+            name: key(function).to_owned(),
+            functions: 0,
+            internal_calls: 0,
+            incoming_calls: 0,
+            outgoing_calls: 0,
+            max_fan_in: 0,
+            max_fan_out: 0,
+            max_depth: None,
+            max_betweenness: 0.0,
+        });
+        group.functions += 1;
+        group.max_fan_in = group.max_fan_in.max(function.fan_in);
+        group.max_fan_out = group.max_fan_out.max(function.fan_out);
+        group.max_depth = group.max_depth.max(function.depth);
+        group.max_betweenness = group.max_betweenness.max(function.betweenness);
+    }
+    for call in &graph.calls {
+        let caller = group_of.get(call.caller.as_str()).copied();
+        let callee = group_of.get(call.callee.as_str()).copied();
+        match (caller, callee) {
+            (Some(caller), Some(callee)) if caller == callee => groups.get_mut(caller).unwrap().internal_calls += 1,
+            (caller, callee) => {
+                if let Some(caller) = caller {
+                    groups.get_mut(caller).unwrap().outgoing_calls += 1;
+                }
+                if let Some(callee) = callee {
+                    groups.get_mut(callee).unwrap().incoming_calls += 1;
+                }
+            }
+        }
+    }
+    groups.into_values().collect()
+}
+
+// The longest path from the roots on the graph of the strongly connected components.
+fn depths(adjacency: &[Vec<usize>], roots: impl Iterator<Item = usize>) -> Vec<Option<usize>> {
+    let sccs = tarjan(adjacency);
+    let mut component = vec![0; adjacency.len()];
+    for (i, scc) in sccs.iter().enumerate() {
+        for node in scc {
+            component[*node] = i;
+        }
+    }
+
+    let mut depth: Vec<Option<usize>> = vec![None; sccs.len()];
+    for root in roots {
+        depth[component[root]] = Some(0);
+    }
+    // Tarjan finds the components in reverse topological order
+    for i in (0..sccs.len()).rev() {
+        let Some(d) = depth[i] else {
+            continue;
+        };
+        for node in &sccs[i] {
+            for next in &adjacency[*node] {
+                let c = component[*next];
+                if c != i {
+                    depth[c] = depth[c].max(Some(d + 1));
+                }
+            }
+        }
+    }
+    (0..adjacency.len()).map(|node| depth[component[node]]).collect()
+}
+
+// Brandes' algorithm for unweighted directed graphs.
+fn betweenness(adjacency: &[Vec<usize>]) -> Vec<f64> {
+    let n = adjacency.len();
+    let mut centrality = vec![0.0; n];
+    for source in 0..n {
+        let mut stack = Vec::new();
+        let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); n];
+        let mut paths = vec![0.0; n];
+        let mut distance: Vec<Option<usize>> = vec![None; n];
+        paths[source] = 1.0;
+        distance[source] = Some(0);
+        let mut queue = VecDeque::from([source]);
+        while let Some(node) = queue.pop_front() {
+            stack.push(node);
+            let d = distance[node].unwrap();
+            for &next in &adjacency[node] {
+                if distance[next].is_none() {
+                    distance[next] = Some(d + 1);
+                    queue.push_back(next);
+                }
+                if distance[next] == Some(d + 1) {
+                    paths[next] += paths[node];
+                    predecessors[next].push(node);
+                }
+            }
+        }
+
+        let mut dependency = vec![0.0; n];
+        while let Some(node) = stack.pop() {
+            for &pred in &predecessors[node] {
+                dependency[pred] += paths[pred] / paths[node] * (1.0 + dependency[node]);
+            }
+            if node != source {
+                centrality[node] += dependency[node];
+            }
+        }
+    }
+    centrality
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{call, graph};
+
+    #[test]
+    fn betweenness_on_a_path() {
+        // 0 -> 1 -> 2 -> 3
+        let adjacency = vec![vec![1], vec![2], vec![3], vec![]];
+        assert_eq!(betweenness(&adjacency), [0.0, 2.0, 2.0, 0.0]);
+    }
+
+    #[test]
+    fn betweenness_splits_among_shortest_chains() {
+        // 0 -> 1 -> 3 and 0 -> 2 -> 3, 3 -> 4
+        let adjacency = vec![vec![1, 2], vec![3], vec![3], vec![4], vec![]];
+        // 0 to 3 and 0 to 4 go half through 1 and half through 2
+        assert_eq!(betweenness(&adjacency), [0.0, 1.0, 1.0, 3.0, 0.0]);
+    }
+
+    #[test]
+    fn depth_is_the_longest_chain_on_the_condensation() {
+        // 0 -> 1 <-> 2 -> 3, 0 -> 3, 4 unreached
+        let adjacency = vec![vec![1, 3], vec![2], vec![1, 3], vec![], vec![3]];
+        assert_eq!(depths(&adjacency, [0].into_iter()), [Some(0), Some(1), Some(1), Some(2), None]);
+    }
+
+    #[test]
+    fn function_and_module_metrics() {
+        let mut graph = graph(
+            &["a::main", "a::m::f", "a::m::g", "a::n::h"],
+            vec![
+                call("a::main", "a::m::f", 1),
+                call("a::main", "a::n::h", 2),
+                call("a::m::f", "a::m::g", 3),
+                call("a::m::f", "a::n::h", 4),
+                call("a::n::h", "std::io::stdout", 5),
+            ],
+        );
+        for function in &mut graph.functions {
+            function.public = false;
+            function.entry = function.def_path == "a::main";
+        }
+        let metrics = Metrics::new(&graph, &[]);
+        let functions: Vec<(&str, usize, usize, Option<usize>)> = metrics
+            .functions
+            .iter()
+            .map(|function| (function.def_path.as_str(), function.fan_in, function.fan_out, function.depth))
+            .collect();
+        assert_eq!(
+            functions,
+            [
+                ("a::m::f", 1, 2, Some(1)),
+                ("a::m::g", 1, 0, Some(2)),
+                ("a::main", 0, 2, Some(0)),
+                ("a::n::h", 2, 1, Some(2)),
+            ]
+        );
+
+        let m = metrics.modules.iter().find(|module| module.name == "a::m").unwrap();
+        assert_eq!((m.functions, m.internal_calls, m.incoming_calls, m.outgoing_calls), (2, 1, 1, 1));
+        let n = metrics.modules.iter().find(|module| module.name == "a::n").unwrap();
+        assert_eq!((n.functions, n.internal_calls, n.incoming_calls, n.outgoing_calls), (1, 0, 2, 1));
+        assert_eq!(metrics.crates.len(), 1);
+        assert_eq!(metrics.crates[0].internal_calls, 4);
+        assert_eq!(metrics.crates[0].outgoing_calls, 1);
+    }
+
+    #[test]
+    fn csv_quotes_fields() {
+        assert_eq!(csv_field("a::<b as c::D<e, f>>::g"), "\"a::<b as c::D<e, f>>::g\"");
+        assert_eq!(csv_field("a::b"), "a::b");
+    }
+}
@@ -0,0 +1,273 @@
+//! The `--backend mir` call collection.
+//!
+//! Calls are read from the `Call` and `Drop` terminators of `optimized_mir`, so
+//! the calls hidden by desugaring (operators, `Deref`, `?`, `for` loops, drops)
+//! show up as well. Bodies are walked from the non generic functions of the crate
+//! with concrete generic arguments, so calls of generic functions resolve to the
+//! implementations actually used. Bodies never reached that way, e.g. generic
+//! functions of a library, are walked once with their own generic parameters.
+//! The bounds and arithmetic checks of `Assert` terminators are recorded as panic sites.
+//!
+//! The driver turns the MIR inliner off (`-Zinline-mir=no`), which would otherwise remove
+//! the calls to small functions under `-O`.
+
+use std::collections::{HashMap, HashSet};
+
+use rustc_hir::def_id::DefId;
+use rustc_middle::mir::{AssertKind, BasicBlock, Body, TerminatorKind};
+use rustc_middle::ty::{self, EarlyBinder, GenericArgsRef, Instance, InstanceKind, ParamEnv, Ty, TyCtxt, TypeVisitableExt};
+
+use crate::graph::{CallKind, PanicKind};
+use crate::visitor::CallgraphVisitor;
+
+// Bounds the instances walked per function, polymorphic recursion would never end.
+const MAX_INSTANCES_PER_FN: usize = 64;
+
+// A body to walk: the function with its generic arguments, `None` for the body
+// with its own generic parameters.
+type Item<'tcx> = (DefId, Option<GenericArgsRef<'tcx>>, ParamEnv<'tcx>);
+
+/// Replaces the calls found by the HIR visitor with the calls found in MIR.
+pub(crate) fn collect_calls<'tcx>(tcx: TyCtxt<'tcx>, visitor: &mut CallgraphVisitor<'tcx>) {
+    visitor.clear_calls();
+
+    let bodies: Vec<DefId> = tcx
+        .hir()
+        .body_owners()
+        .map(|def_id| def_id.to_def_id())
+        .filter(|def_id| tcx.def_kind(*def_id).is_fn_like() && tcx.is_mir_available(*def_id))
+        .collect();
+
+    let mut collector = MirCollector {
+        tcx,
+        visitor,
+        seen: HashSet::new(),
+        instances: HashMap::new(),
+        queue: Vec::new(),
+    };
+
+    // monomorphic roots first, they tell which instances of generic functions are used
+    for def_id in &bodies {
+        if !tcx.is_closure_like(*def_id) && !tcx.generics_of(*def_id).requires_monomorphization(tcx) {
+            collector.push(*def_id, Some(ty::List::empty()), ParamEnv::reveal_all());
+        }
+    }
+    collector.run();
+
+    for def_id in &bodies {
+        if !collector.instances.contains_key(def_id) {
+            collector.push(*def_id, None, tcx.param_env_reveal_all_normalized(*def_id));
+        }
+    }
+    collector.run();
+}
+
+struct MirCollector<'a, 'tcx> {
+    tcx: TyCtxt<'tcx>,
+    visitor: &'a mut CallgraphVisitor<'tcx>,
+    seen: HashSet<(DefId, Option<GenericArgsRef<'tcx>>)>,
+    // number of walked instances per function
+    instances: HashMap<DefId, usize>,
+    queue: Vec<Item<'tcx>>,
+}
+
+impl<'a, 'tcx> MirCollector<'a, 'tcx> {
+    fn push(&mut self, def_id: DefId, args: Option<GenericArgsRef<'tcx>>, param_env: ParamEnv<'tcx>) {
+        if !def_id.is_local() || !self.tcx.is_mir_available(def_id) {
+            return;
+        }
+        let count = self.instances.entry(def_id).or_default();
+        if *count >= MAX_INSTANCES_PER_FN || !self.seen.insert((def_id, args)) {
+            return;
+        }
+        *count += 1;
+        self.queue.push((def_id, args, param_env));
+    }
+
+    fn run(&mut self) {
+        while let Some(item) = self.queue.pop() {
+            self.walk_body(item);
+        }
+    }
+
+    fn walk_body(&mut self, (caller, args, param_env): Item<'tcx>) {
+        let tcx = self.tcx;
+        let body = tcx.optimized_mir(caller);
+        let caller_hir_id = tcx.local_def_id_to_hir_id(caller.expect_local());
+
+        for (bb, data) in body.basic_blocks.iter_enumerated() {
+            // the calls and drops on the unwind path mirror those of the normal path
+            if data.is_cleanup {
+                continue;
+            }
+            let terminator = data.terminator();
+            let source_info = terminator.source_info;
+            let call_expr = source_info.scope.lint_root(&body.source_scopes).unwrap_or(caller_hir_id);
+            let targets = match &terminator.kind {
+                TerminatorKind::Assert { msg, .. } => {
+                    if let Some(kind) = panic_kind(msg) {
+                        self.visitor.record_panic_site(caller, kind, source_info.span);
+                    }
+                    continue;
+                }
+                TerminatorKind::Call { func, .. } => {
+                    let Some(func_ty) = self.instantiate(args, param_env, func.ty(body, tcx)) else {
+                        continue;
+                    };
+                    self.call_targets(func_ty, param_env)
+                }
+                TerminatorKind::Drop { place, .. } => {
+                    let Some(ty) = self.instantiate(args, param_env, place.ty(body, tcx).ty) else {
+                        continue;
+                    };
+                    self.drop_targets(ty)
+                }
+                _ => continue,
+            };
+            if targets.is_empty() {
+                continue;
+            }
+
+            let constraint_depth = constraint_depth(body, bb);
+            for (kind, callee, callee_args) in targets {
+                self.visitor
+                    .record_call(kind, caller, callee, call_expr, source_info.span, constraint_depth);
+                if kind == CallKind::Static {
+                    let callee_param_env = if callee_args.is_some_and(|args| args.has_param()) {
+                        param_env
+                    } else {
+                        ParamEnv::reveal_all()
+                    };
+                    self.push(callee, callee_args, callee_param_env);
+                }
+            }
+        }
+    }
+
+    fn instantiate(&self, args: Option<GenericArgsRef<'tcx>>, param_env: ParamEnv<'tcx>, ty: Ty<'tcx>) -> Option<Ty<'tcx>> {
+        match args {
+            Some(args) => self.tcx.try_instantiate_and_normalize_erasing_regions(args, param_env, EarlyBinder::bind(ty)).ok(),
+            None => self.tcx.try_normalize_erasing_regions(param_env, ty).ok(),
+        }
+    }
+
+    // The functions called through a value of type `func_ty`, with their kind and
+    // generic arguments if known. Calls through function pointers are unknown.
+    fn call_targets(&self, func_ty: Ty<'tcx>, param_env: ParamEnv<'tcx>) -> Vec<(CallKind, DefId, Option<GenericArgsRef<'tcx>>)> {
+        let tcx = self.tcx;
+        let ty::FnDef(def_id, args) = *func_ty.kind() else {
+            return vec![];
+        };
+        let instance = match Instance::try_resolve(tcx, param_env, def_id, args) {
+            Ok(Some(instance)) => instance,
+            // a trait method called on a generic parameter
+            Ok(None) => return vec![(self.kind_of(def_id, true), def_id, None)],
+            Err(_) => return vec![],
+        };
+
+        match instance.def {
+            InstanceKind::Item(callee) => vec![(self.kind_of(callee, false), callee, Some(instance.args))],
+            InstanceKind::Virtual(callee, _) => vec![(self.kind_of(callee, true), callee, None)],
+            InstanceKind::DropGlue(_, Some(ty)) => self.drop_targets(ty),
+            InstanceKind::DropGlue(_, None) => vec![],
+            _ => {
+                // shims calling a closure or function item, e.g. `FnOnce::call_once`
+                // on a closure, are calls of that closure or function
+                match args.types().next().map(|ty| *ty.kind()) {
+                    Some(ty::Closure(closure, closure_args)) => {
+                        vec![(self.kind_of(closure, false), closure, Some(closure_args))]
+                    }
+                    Some(ty::FnDef(..)) if args.type_at(0) != func_ty => {
+                        self.call_targets(args.type_at(0), param_env)
+                    }
+                    _ => {
+                        let callee = instance.def_id();
+                        vec![(self.kind_of(callee, false), callee, Some(instance.args))]
+                    }
+                }
+            }
+        }
+    }
+
+    // Dropping a value calls its `Drop` impl, or the drop glue of its fields.
+    fn drop_targets(&self, ty: Ty<'tcx>) -> Vec<(CallKind, DefId, Option<GenericArgsRef<'tcx>>)> {
+        let tcx = self.tcx;
+        if let ty::Adt(adt, args) = *ty.kind() {
+            if let Some(destructor) = adt.destructor(tcx) {
+                return vec![(self.kind_of(destructor.did, false), destructor.did, Some(args))];
+            }
+        }
+        if ty.has_param() {
+            return vec![];
+        }
+        match Instance::resolve_drop_in_place(tcx, ty).def {
+            InstanceKind::DropGlue(def_id, Some(_)) => vec![(CallKind::NonLocal, def_id, None)],
+            _ => vec![],
+        }
+    }
+
+    // Calls of trait methods that are not resolved to an implementation are dynamic,
+    // like in the HIR visitor.
+    fn kind_of(&self, callee: DefId, unresolved: bool) -> CallKind {
+        if !callee.is_local() {
+            CallKind::NonLocal
+        } else if unresolved {
+            CallKind::Dynamic
+        } else {
+            CallKind::Static
+        }
+    }
+}
+
+// The checks of `Assert` terminators that may fail in correct programs.
+fn panic_kind<O>(msg: &AssertKind<O>) -> Option<PanicKind> {
+    match msg {
+        AssertKind::BoundsCheck { .. } => Some(PanicKind::Index),
+        AssertKind::Overflow(..) | AssertKind::OverflowNeg(_) => Some(PanicKind::Overflow),
+        AssertKind::DivisionByZero(_) | AssertKind::RemainderByZero(_) => Some(PanicKind::DivisionByZero),
+        _ => None,
+    }
+}
+
+// Number of branches (`if`, `match`, loop conditions) guarding `bb`: the switches
+// dominating `bb` with a successor from which `bb` cannot be reached.
+fn constraint_depth(body: &Body<'_>, bb: BasicBlock) -> usize {
+    let dominators = body.basic_blocks.dominators();
+    let mut depth = 0;
+    let mut cur = bb;
+    while let Some(dom) = dominators.immediate_dominator(cur) {
+        let data = &body.basic_blocks[dom];
+        if let TerminatorKind::SwitchInt { .. } = data.terminator().kind {
+            // the `otherwise` arm of an exhaustive `match` is unreachable
+            if data
+                .terminator()
+                .successors()
+                .filter(|succ| !matches!(body.basic_blocks[*succ].terminator().kind, TerminatorKind::Unreachable))
+                .any(|succ| !reaches(body, succ, bb))
+            {
+                depth += 1;
+            }
+        }
+        cur = dom;
+    }
+    depth
+}
+
+fn reaches(body: &Body<'_>, from: BasicBlock, to: BasicBlock) -> bool {
+    let mut seen = HashSet::new();
+    let mut stack = vec![from];
+    while let Some(bb) = stack.pop() {
+        if bb == to {
+            return true;
+        }
+        if !seen.insert(bb) {
+            continue;
+        }
+        let data = &body.basic_blocks[bb];
+        if data.is_cleanup {
+            continue;
+        }
+        stack.extend(data.terminator().successors());
+    }
+    false
+}
@@ -0,0 +1,199 @@
+//! Panic reachability for `--panics`: which functions may panic, and a call chain proving it.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{CallGraph, Function, PanicSite};
+use crate::index::{chain, hops, shortest_chains, Hop};
+
+// Functions that always panic.
+const PANICKING_PREFIXES: &[&str] = &["core::panicking::", "std::panicking::", "std::rt::begin_panic"];
+
+// Methods that panic on some inputs, by a part of their def path naming the self type or trait.
+const PANICKING_METHODS: &[(&str, &[&str])] = &[
+    ("core::<core::option::Option<", &["unwrap", "expect"]),
+    ("core::<core::result::Result<", &["unwrap", "expect", "unwrap_err", "expect_err"]),
+    ("core::<core::cell::RefCell<", &["borrow", "borrow_mut"]),
+    (" as core::ops::index::Index<", &["index"]),
+    (" as core::ops::index::IndexMut<", &["index_mut"]),
+];
+
+/// Whether the function of another crate with this def path may panic, e.g. `Option::unwrap`.
+pub fn is_panicking(def_path: &str) -> bool {
+    if PANICKING_PREFIXES.iter().any(|prefix| def_path.starts_with(prefix)) {
+        return true;
+    }
+    let Some((owner, name)) = def_path.rsplit_once("::") else {
+        return false;
+    };
+    PANICKING_METHODS
+        .iter()
+        .any(|(pattern, names)| owner.contains(pattern) && names.contains(&name))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Panics {
+    pub functions: Vec<PanicFunction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PanicFunction {
+    pub def_path: String,
+    pub may_panic: bool,
+    /// The shortest call chain from the function to a panic: to a panicking function of
+    /// another crate, or to the function with the panicking `operation`.
+    pub witness: Vec<Hop>,
+    /// The operation ending the witness, if it does not end with a call.
+    pub operation: Option<PanicSite>,
+}
+
+impl Panics {
+    /// A function may panic if it has a panic site, calls a panicking function of another
+    /// crate, or calls a function that may panic. Trait methods lead to their implementations,
+    /// and functions to the closures they define and pass to a call.
+    pub fn new(graph: &CallGraph) -> Panics {
+        let hops = hops(graph);
+        // the functions with their own panic sites first
+        let operations = graph
+            .functions
+            .iter()
+            .filter(|function| !function.panic_sites.is_empty())
+            .map(|function| (function.id.as_str(), None));
+        let calls = hops
+            .iter()
+            .filter(|hop| is_panicking(&hop.callee))
+            .map(|hop| (hop.caller_id.as_str(), Some(hop)));
+        let first_hops = shortest_chains(&hops, operations.chain(calls));
+        let by_id: HashMap<&str, &Function> =
+            graph.functions.iter().map(|function| (function.id.as_str(), function)).collect();
+
+        let mut functions: Vec<(&str, PanicFunction)> = graph
+            .functions
+            .iter()
+            .map(|function| {
+                let (witness, end) = chain(&first_hops, &function.id);
+                let operation = match first_hops.get(end) {
+                    Some(None) => by_id.get(end).and_then(|function| function.panic_sites.first().cloned()),
+                    _ => None,
+                };
+                let panic_function = PanicFunction {
+                    def_path: function.def_path.clone(),
+                    may_panic: first_hops.contains_key(function.id.as_str()),
+                    witness,
+                    operation,
+                };
+                (function.id.as_str(), panic_function)
+            })
+            .collect();
+        functions.sort_by(|(a_id, a), (b_id, b)| a.def_path.cmp(&b.def_path).then(a_id.cmp(b_id)));
+        let functions = functions.into_iter().map(|(_, function)| function).collect();
+        Panics { functions }
+    }
+
+    /// Prints the human readable report used by `--format text`.
+    pub fn dump(&self) {
+        let (panicking, safe): (Vec<&PanicFunction>, Vec<&PanicFunction>) =
+            self.functions.iter().partition(|function| function.may_panic);
+        println!("May Panic ({}):", panicking.len());
+        for function in panicking {
+            println!("  {}", function.def_path);
+            for hop in &function.witness {
+                match &hop.span {
+                    Some(span) => println!("      {} --- {} ({}) at {}", hop.caller, hop.callee, hop.kind, span),
+                    None => println!("      {} --- {} ({})", hop.caller, hop.callee, hop.kind),
+                }
+            }
+            if let Some(site) = &function.operation {
+                match &site.span {
+                    Some(span) => println!("      {} at {}", site.kind, span),
+                    None => println!("      {}", site.kind),
+                }
+            }
+        }
+
+        println!("\nNever Panics ({}):", safe.len());
+        for function in safe {
+            println!("  {}", function.def_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{CallKind, PanicKind};
+    use crate::testing::{call, function, graph, location, method_impls};
+
+    fn function_of<'a>(panics: &'a Panics, def_path: &str) -> &'a PanicFunction {
+        panics.functions.iter().find(|function| function.def_path == def_path).unwrap()
+    }
+
+    fn kinds(function: &PanicFunction) -> Vec<&str> {
+        function.witness.iter().map(|hop| hop.kind.as_str()).collect()
+    }
+
+    #[test]
+    fn panicking_functions_of_other_crates() {
+        // def paths as the graph names them
+        for def_path in [
+            "core::panicking::panic_fmt",
+            "std::rt::begin_panic",
+            "core::<core::option::Option<T>>::unwrap",
+            "core::<core::result::Result<T, E>>::expect_err",
+            "core::<core::cell::RefCell<T>>::borrow_mut",
+            "core::<str as core::ops::index::Index<I>>::index",
+            "std::<std::collections::hash::map::HashMap<K, V, S> as core::ops::index::Index<&Q>>::index",
+        ] {
+            assert!(is_panicking(def_path), "{}", def_path);
+        }
+        for def_path in [
+            "core::<core::option::Option<T>>::unwrap_or",
+            "core::<core::fmt::Arguments>::new_v1",
+            "core::<str>::len",
+            "core::<[T]>::get",
+            "a::unwrap",
+        ] {
+            assert!(!is_panicking(def_path), "{}", def_path);
+        }
+    }
+
+    #[test]
+    fn witness_to_a_call_and_to_an_operation() {
+        let mut unwrap = call("a::helper", "core::<core::option::Option<T>>::unwrap", 3);
+        unwrap.kind = CallKind::NonLocal;
+        let mut graph = graph(
+            &["a::opt", "a::helper", "a::first", "a::safe"],
+            vec![call("a::opt", "a::helper", 1), unwrap, call("a::safe", "a::safe", 4)],
+        );
+        graph.functions[2].panic_sites.push(PanicSite { kind: PanicKind::Index, span: Some(location(2)) });
+
+        let panics = Panics::new(&graph);
+        let opt = function_of(&panics, "a::opt");
+        assert!(opt.may_panic);
+        assert_eq!(kinds(opt), ["static", "non_local"]);
+        assert_eq!(opt.witness[1].callee, "core::<core::option::Option<T>>::unwrap");
+        assert!(opt.operation.is_none());
+        let first = function_of(&panics, "a::first");
+        assert!(first.witness.is_empty());
+        assert_eq!(first.operation.as_ref().unwrap().kind, PanicKind::Index);
+        assert!(!function_of(&panics, "a::safe").may_panic);
+    }
+
+    #[test]
+    fn trait_methods_lead_to_their_implementations() {
+        let mut dynamic = call("a::run", "a::Tr::m", 1);
+        dynamic.kind = CallKind::Dynamic;
+        let mut graph = graph(&["a::run", "a::<a::S as a::Tr>::m", "a::<a::T as a::Tr>::m"], vec![dynamic]);
+        graph.functions[2].panic_sites.push(PanicSite { kind: PanicKind::DivisionByZero, span: Some(location(2)) });
+        graph.method_decls.push(function("a::Tr::m"));
+        graph.method_impls.push(method_impls("a::Tr::m", &["a::<a::S as a::Tr>::m", "a::<a::T as a::Tr>::m"]));
+
+        let panics = Panics::new(&graph);
+        let run = function_of(&panics, "a::run");
+        assert_eq!(kinds(run), ["dynamic", "impl"]);
+        assert_eq!(run.witness[1].callee, "a::<a::T as a::Tr>::m");
+        assert_eq!(run.operation.as_ref().unwrap().kind, PanicKind::DivisionByZero);
+        assert!(!function_of(&panics, "a::<a::S as a::Tr>::m").may_panic);
+    }
+}
@@ -0,0 +1,609 @@
+//! `callgraph query`: questions about a call graph saved with `--format json`.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet};
+use std::io::Read;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{take_flag_value, OutputFormat};
+use crate::failure::FailurePaths;
+use crate::git::DiffImpact;
+use crate::graph::{Call, CallGraph, CallSite, SCHEMA_VERSION};
+use crate::impact::Impact;
+use crate::index;
+use crate::panics::Panics;
+use crate::print_json;
+use crate::scc::Recursion;
+use crate::unsafety::Unsafety;
+
+/// Runs `callgraph query <query> [options]`, `args` starting with the query name.
+pub fn run(mut args: Vec<String>) -> Result<(), String> {
+    if args.is_empty() {
+        return Err("missing query, expected `path`, `callers`, `impact`, `recursion`, `failure`, `panics` or `unsafe`".to_owned());
+    }
+    let query = args.remove(0);
+    let graph_file = take_flag_value(&mut args, "--graph")?;
+    let format = match take_flag_value(&mut args, "--format")? {
+        Some(format) => format.parse()?,
+        None => OutputFormat::Text,
+    };
+    if matches!(format, OutputFormat::Dot | OutputFormat::Csv) {
+        return Err("queries print a report, use `--format text` or `--format json`".to_owned());
+    }
+
+    match query.as_str() {
+        "path" => {
+            let query = PathQuery::from_args(&mut args)?;
+            check_no_args_left(&args)?;
+            let graph = load_graph(graph_file.as_deref())?;
+            let chains = query.run(&graph)?;
+            match format {
+                OutputFormat::Json => print_json(&chains),
+                _ => dump_chains(&query, &chains),
+            }
+        }
+        "callers" => {
+            let of = take_flag_value(&mut args, "--of")?.ok_or("missing `--of`")?;
+            let depth = take_depth(&mut args)?;
+            check_no_args_left(&args)?;
+            let graph = load_graph(graph_file.as_deref())?;
+            let changed = of.split(',').map(|name| resolve(&graph, name)).collect::<Result<Vec<_>, _>>()?.concat();
+            let impact = Impact::new(&graph, &changed, depth);
+            match format {
+                OutputFormat::Json => print_json(&impact),
+                _ => impact.dump(),
+            }
+        }
+        "impact" => {
+            let base = take_flag_value(&mut args, "--base")?.unwrap_or_else(|| "HEAD".to_owned());
+            let depth = take_depth(&mut args)?;
+            check_no_args_left(&args)?;
+            let graph = load_graph(graph_file.as_deref())?;
+            let impact = DiffImpact::new(&graph, &base, depth)?;
+            match format {
+                OutputFormat::Json => print_json(&impact),
+                _ => impact.dump(),
+            }
+        }
+        "recursion" => {
+            check_no_args_left(&args)?;
+            let graph = load_graph(graph_file.as_deref())?;
+            let recursion = Recursion::new(&graph);
+            match format {
+                OutputFormat::Json => print_json(&recursion),
+                _ => recursion.dump(),
+            }
+        }
+        "failure" => {
+            check_no_args_left(&args)?;
+            let graph = load_graph(graph_file.as_deref())?;
+            let failure = FailurePaths::new(&graph);
+            match format {
+                OutputFormat::Json => print_json(&failure),
+                _ => failure.dump(),
+            }
+        }
+        "panics" => {
+            check_no_args_left(&args)?;
+            let graph = load_graph(graph_file.as_deref())?;
+            let panics = Panics::new(&graph);
+            match format {
+                OutputFormat::Json => print_json(&panics),
+                _ => panics.dump(),
+            }
+        }
+        "unsafe" => {
+            check_no_args_left(&args)?;
+            let graph = load_graph(graph_file.as_deref())?;
+            let unsafety = Unsafety::new(&graph);
+            match format {
+                OutputFormat::Json => print_json(&unsafety),
+                _ => unsafety.dump(),
+            }
+        }
+        _ => {
+            return Err(format!(
+                "unknown query `{}`, expected `path`, `callers`, `impact`, `recursion`, `failure`, `panics` or `unsafe`",
+                query
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn take_depth(args: &mut Vec<String>) -> Result<Option<usize>, String> {
+    match take_flag_value(args, "--depth")? {
+        Some(depth) => Ok(Some(depth.parse().map_err(|_| format!("invalid number `{}` for `--depth`", depth))?)),
+        None => Ok(None),
+    }
+}
+
+fn check_no_args_left(args: &[String]) -> Result<(), String> {
+    match args.first() {
+        Some(arg) => Err(format!("unexpected argument `{}`", arg)),
+        None => Ok(()),
+    }
+}
+
+/// Reads a graph printed by `--format json` from `file`, or from stdin.
+pub fn load_graph(file: Option<&str>) -> Result<CallGraph, String> {
+    let mut json = String::new();
+    match file {
+        Some(file) => {
+            json = std::fs::read_to_string(file).map_err(|e| format!("could not read `{}`: {}", file, e))?;
+        }
+        None => {
+            std::io::stdin()
+                .read_to_string(&mut json)
+                .map_err(|e| format!("could not read the graph from stdin: {}", e))?;
+        }
+    }
+    let graph: CallGraph = serde_json::from_str(&json).map_err(|e| format!("invalid call graph: {}", e))?;
+    if graph.schema_version != SCHEMA_VERSION {
+        return Err(format!(
+            "the call graph has schema version {}, expected {}; analyze the crate again",
+            graph.schema_version, SCHEMA_VERSION
+        ));
+    }
+    Ok(graph)
+}
+
+/// What a call chain costs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Weight {
+    /// Every call counts as one.
+    #[default]
+    Hops,
+    /// The `constraint_depth` of the calls, preferring chains guarded by fewer conditions.
+    ConstraintDepth,
+}
+
+impl FromStr for Weight {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hops" => Ok(Weight::Hops),
+            "constraint-depth" => Ok(Weight::ConstraintDepth),
+            _ => Err(format!("unknown weight `{}`, expected `hops` or `constraint-depth`", s)),
+        }
+    }
+}
+
+/// `query path`: the cheapest call chains from one function to another.
+#[derive(Debug, Clone)]
+pub struct PathQuery {
+    pub from: String,
+    pub to: String,
+    /// Number of chains to find.
+    pub k: usize,
+    /// Only follow hops of these kinds, see [`HOP_KINDS`].
+    pub kinds: Vec<String>,
+    pub weight: Weight,
+}
+
+/// A chain of calls, the callee of each hop being the caller of the next.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CallChain {
+    /// The sum of the weights of the hops.
+    pub cost: usize,
+    pub hops: Vec<Hop>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hop {
+    pub caller: String,
+    pub callee: String,
+    /// The call kind, `impl` from a trait method declaration to an implementation, or
+    /// `closure` from a function to a closure it defines and passes on.
+    pub kind: String,
+    /// 0 for `impl` and `closure` hops.
+    pub constraint_depth: usize,
+    /// The call sites, none for `impl` and `closure` hops.
+    pub sites: Vec<CallSite>,
+}
+
+/// The kinds of hops `--kinds` selects from.
+pub const HOP_KINDS: &[&str] = &["static", "dynamic", "non_local", "impl", "closure"];
+
+// A hop of the graph, with the call it stands for unless it is an `impl` or `closure` hop.
+#[derive(Clone, Copy)]
+struct Edge<'a> {
+    hop: &'a index::Hop,
+    call: Option<&'a Call>,
+}
+
+// (weight, hops), so that chains of the same weight prefer fewer hops
+type Cost = (usize, usize);
+
+impl PathQuery {
+    /// Parses and removes `--from`, `--to`, `-k`, `--kinds` and `--weight` from `args`.
+    pub fn from_args(args: &mut Vec<String>) -> Result<PathQuery, String> {
+        let from = take_flag_value(args, "--from")?.ok_or("missing `--from`")?;
+        let to = take_flag_value(args, "--to")?.ok_or("missing `--to`")?;
+        let k = match take_flag_value(args, "-k")? {
+            Some(k) => k.parse().map_err(|_| format!("invalid number `{}` for `-k`", k))?,
+            None => 1,
+        };
+        let kinds = match take_flag_value(args, "--kinds")? {
+            Some(kinds) => kinds
+                .split(',')
+                .map(|kind| match HOP_KINDS.contains(&kind) {
+                    true => Ok(kind.to_owned()),
+                    false => Err(format!("unknown kind `{}`, expected one of: {}", kind, HOP_KINDS.join(", "))),
+                })
+                .collect::<Result<_, _>>()?,
+            None => HOP_KINDS.iter().map(|kind| kind.to_string()).collect(),
+        };
+        let weight = match take_flag_value(args, "--weight")? {
+            Some(weight) => weight.parse()?,
+            None => Weight::Hops,
+        };
+        Ok(PathQuery { from, to, k, kinds, weight })
+    }
+
+    /// Finds the `k` cheapest chains without repeated functions, cheapest first. Chains
+    /// follow the hops of [`index::hops`]: calls, and trait method declarations into their
+    /// implementations and functions into the closures they pass on.
+    pub fn run(&self, graph: &CallGraph) -> Result<Vec<CallChain>, String> {
+        let from = resolve_one(graph, &self.from)?;
+        let to = resolve_one(graph, &self.to)?;
+        if from == to {
+            return Err(format!("`--from` and `--to` are both `{}`", from));
+        }
+
+        // the cheapest call of each kind between two functions
+        let mut calls: HashMap<(&str, &str, String), &Call> = HashMap::new();
+        for call in &graph.calls {
+            let best = calls.entry((&call.caller_id, &call.callee_id, call.kind.to_string())).or_insert(call);
+            if self.call_weight(call) < self.call_weight(best) {
+                *best = call;
+            }
+        }
+        // the cheapest hop of the selected kinds between each pair of functions
+        let hops = index::hops(graph);
+        let mut edges: HashMap<&str, HashMap<&str, Edge>> = HashMap::new();
+        for hop in &hops {
+            if !self.kinds.contains(&hop.kind) {
+                continue;
+            }
+            let call = calls.get(&(hop.caller_id.as_str(), hop.callee_id.as_str(), hop.kind.clone())).copied();
+            let edge = Edge { hop, call };
+            let best = edges.entry(&hop.caller_id).or_default().entry(&hop.callee_id).or_insert(edge);
+            if self.edge_weight(edge) < self.edge_weight(*best) {
+                *best = edge;
+            }
+        }
+        let search = Search { query: self, edges: &edges };
+
+        // Yen's algorithm
+        let mut found: Vec<(Cost, Vec<&str>)> = Vec::new();
+        let mut candidates: BTreeSet<(Cost, Vec<&str>)> = BTreeSet::new();
+        if let Some(path) = search.shortest(from, to, &HashSet::new(), &HashSet::new()) {
+            candidates.insert((search.cost(&path), path));
+        }
+        while found.len() < self.k {
+            let Some(next) = candidates.pop_first() else {
+                break;
+            };
+            let last = next.1.clone();
+            found.push(next);
+
+            for i in 0..last.len() - 1 {
+                let root = &last[..=i];
+                // leave the chains found so far at the spur node
+                let banned_edges: HashSet<(&str, &str)> = found
+                    .iter()
+                    .filter(|(_, path)| path.len() > i + 1 && path[..=i] == *root)
+                    .map(|(_, path)| (path[i], path[i + 1]))
+                    .collect();
+                let banned_nodes: HashSet<&str> = root[..i].iter().copied().collect();
+                if let Some(spur) = search.shortest(last[i], to, &banned_nodes, &banned_edges) {
+                    let mut path = root[..i].to_vec();
+                    path.extend(spur);
+                    if !found.iter().any(|(_, found)| *found == path) {
+                        candidates.insert((search.cost(&path), path));
+                    }
+                }
+            }
+        }
+
+        Ok(found
+            .into_iter()
+            .map(|((cost, _), path)| CallChain {
+                cost,
+                hops: path
+                    .windows(2)
+                    .map(|pair| {
+                        let Edge { hop, call } = edges[pair[0]][pair[1]];
+                        Hop {
+                            caller: hop.caller.clone(),
+                            callee: hop.callee.clone(),
+                            kind: hop.kind.clone(),
+                            constraint_depth: call.map_or(0, |call| call.constraint_depth),
+                            sites: call.map_or(Vec::new(), |call| call.sites.clone()),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+
+    fn call_weight(&self, call: &Call) -> usize {
+        match self.weight {
+            Weight::Hops => 1,
+            Weight::ConstraintDepth => call.constraint_depth,
+        }
+    }
+
+    // `impl` and `closure` hops are not guarded by conditions of their own
+    fn edge_weight(&self, edge: Edge) -> usize {
+        match self.weight {
+            Weight::Hops => 1,
+            Weight::ConstraintDepth => edge.call.map_or(0, |call| call.constraint_depth),
+        }
+    }
+}
+
+struct Search<'q, 'a> {
+    query: &'q PathQuery,
+    edges: &'q HashMap<&'a str, HashMap<&'a str, Edge<'a>>>,
+}
+
+impl<'q, 'a> Search<'q, 'a> {
+    fn cost(&self, path: &[&'a str]) -> Cost {
+        let weight = path
+            .windows(2)
+            .map(|pair| self.query.edge_weight(self.edges[pair[0]][pair[1]]))
+            .sum();
+        (weight, path.len() - 1)
+    }
+
+    // Dijkstra, avoiding `banned_nodes` and `banned_edges`.
+    fn shortest(
+        &self,
+        from: &'a str,
+        to: &'a str,
+        banned_nodes: &HashSet<&str>,
+        banned_edges: &HashSet<(&str, &str)>,
+    ) -> Option<Vec<&'a str>> {
+        let mut dist: HashMap<&str, Cost> = HashMap::new();
+        let mut prev: HashMap<&str, &str> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        dist.insert(from, (0, 0));
+        heap.push(Reverse(((0, 0), from)));
+
+        while let Some(Reverse((cost, node))) = heap.pop() {
+            if node == to {
+                let mut path = vec![to];
+                while let Some(p) = prev.get(path.last().unwrap()) {
+                    path.push(p);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if dist.get(node).is_some_and(|best| *best < cost) {
+                continue;
+            }
+            let Some(callees) = self.edges.get(node) else {
+                continue;
+            };
+            for (callee, edge) in callees {
+                if banned_nodes.contains(callee) || banned_edges.contains(&(node, *callee)) {
+                    continue;
+                }
+                let next = (cost.0 + self.query.edge_weight(*edge), cost.1 + 1);
+                if dist.get(callee).map_or(true, |best| next < *best) {
+                    dist.insert(callee, next);
+                    prev.insert(callee, node);
+                    heap.push(Reverse((next, callee)));
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Finds the functions named `name` in the graph, by id: the function with this id, the
+/// functions with this def path, or those whose def path is the only one ending with
+/// `name`, e.g. `Url::parse` for `url::Url::parse`. A def path names several functions
+/// in graphs linking two versions of a crate.
+pub fn resolve<'a>(graph: &'a CallGraph, name: &str) -> Result<Vec<&'a str>, String> {
+    let mut names: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    let functions = graph.functions.iter().chain(&graph.method_decls);
+    let calls = graph
+        .calls
+        .iter()
+        .flat_map(|call| [(call.caller.as_str(), call.caller_id.as_str()), (call.callee.as_str(), call.callee_id.as_str())]);
+    for (def_path, id) in functions.map(|function| (function.def_path.as_str(), function.id.as_str())).chain(calls) {
+        if !def_path.is_empty() {
+            names.entry(def_path).or_default().insert(id);
+        }
+    }
+    if let Some(id) = names.values().flatten().find(|id| **id == name) {
+        return Ok(vec![id]);
+    }
+    if let Some(ids) = names.get(name) {
+        return Ok(ids.iter().copied().collect());
+    }
+
+    let suffix = format!("::{}", name);
+    let matches: Vec<(&str, &BTreeSet<&str>)> =
+        names.iter().map(|(def_path, ids)| (*def_path, ids)).filter(|(def_path, _)| def_path.ends_with(&suffix)).collect();
+    match matches.as_slice() {
+        [(_, ids)] => Ok(ids.iter().copied().collect()),
+        [] => Err(format!("no function `{}` in the call graph", name)),
+        _ => {
+            let def_paths: Vec<&str> = matches.iter().map(|(def_path, _)| *def_path).collect();
+            Err(format!("`{}` is ambiguous, it could be any of: {}", name, def_paths.join(", ")))
+        }
+    }
+}
+
+/// Like [`resolve`], for a name that must be a single function.
+fn resolve_one<'a>(graph: &'a CallGraph, name: &str) -> Result<&'a str, String> {
+    match resolve(graph, name)?.as_slice() {
+        [id] => Ok(id),
+        ids => Err(format!("`{}` is ambiguous, name one of the ids: {}", name, ids.join(", "))),
+    }
+}
+
+fn dump_chains(query: &PathQuery, chains: &[CallChain]) {
+    if chains.is_empty() {
+        println!("No call chain from {} to {}.", query.from, query.to);
+        return;
+    }
+    for (i, chain) in chains.iter().enumerate() {
+        println!("Path {} ({} hops, cost {}):", i + 1, chain.hops.len(), chain.cost);
+        for hop in &chain.hops {
+            println!(
+                "  {} --- {} ({}, Constraint Depth: {})",
+                hop.caller, hop.callee, hop.kind, hop.constraint_depth
+            );
+            for site in &hop.sites {
+                if let Some(span) = &site.span {
+                    println!("      at {} (Constraint Depth: {})", span, site.constraint_depth);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{CallKind, FunctionKind};
+    use crate::testing::{call, graph, id, method_impls};
+
+    fn query(from: &str, to: &str, k: usize) -> PathQuery {
+        PathQuery {
+            from: from.to_owned(),
+            to: to.to_owned(),
+            k,
+            kinds: HOP_KINDS.iter().map(|kind| kind.to_string()).collect(),
+            weight: Weight::Hops,
+        }
+    }
+
+    fn callees(chain: &CallChain) -> Vec<&str> {
+        chain.hops.iter().map(|hop| hop.callee.as_str()).collect()
+    }
+
+    // a -> b -> d and a -> c -> d tie, a -> e -> f -> d is longer
+    fn diamond() -> CallGraph {
+        graph(
+            &["a::a", "a::b", "a::c", "a::d", "a::e", "a::f"],
+            vec![
+                call("a::a", "a::b", 1),
+                call("a::a", "a::c", 2),
+                call("a::a", "a::e", 3),
+                call("a::b", "a::d", 4),
+                call("a::c", "a::d", 5),
+                call("a::e", "a::f", 6),
+                call("a::f", "a::d", 7),
+                // a cycle must not repeat functions
+                call("a::d", "a::a", 8),
+            ],
+        )
+    }
+
+    #[test]
+    fn k_shortest_paths_with_a_tie() {
+        let chains = query("a::a", "a::d", 2).run(&diamond()).unwrap();
+        assert_eq!(chains.len(), 2);
+        assert_eq!(callees(&chains[0]), ["a::b", "a::d"]);
+        assert_eq!(callees(&chains[1]), ["a::c", "a::d"]);
+        assert!(chains.iter().all(|chain| chain.cost == 2));
+    }
+
+    #[test]
+    fn fewer_paths_than_k() {
+        let chains = query("a::a", "a::d", 5).run(&diamond()).unwrap();
+        assert_eq!(chains.len(), 3);
+        assert_eq!(callees(&chains[2]), ["a::e", "a::f", "a::d"]);
+        assert_eq!(chains[2].cost, 3);
+    }
+
+    #[test]
+    fn weight_by_constraint_depth() {
+        let mut graph = diamond();
+        for call in &mut graph.calls {
+            call.constraint_depth = if call.caller == "a::a" && call.callee != "a::e" { 2 } else { 0 };
+        }
+        let mut query = query("a::a", "a::d", 1);
+        query.weight = Weight::ConstraintDepth;
+        let chains = query.run(&graph).unwrap();
+        assert_eq!(callees(&chains[0]), ["a::e", "a::f", "a::d"]);
+        assert_eq!(chains[0].cost, 0);
+    }
+
+    #[test]
+    fn only_selected_kinds() {
+        let mut graph = diamond();
+        for call in &mut graph.calls {
+            if call.callee == "a::b" || call.callee == "a::c" {
+                call.kind = CallKind::Dynamic;
+            }
+        }
+        let mut query = query("a::a", "a::d", 3);
+        query.kinds = vec!["static".to_owned()];
+        let chains = query.run(&graph).unwrap();
+        assert_eq!(chains.len(), 1);
+        assert_eq!(callees(&chains[0]), ["a::e", "a::f", "a::d"]);
+    }
+
+    #[test]
+    fn through_a_trait_impl_and_a_closure() {
+        // `a::main` calls `Tr::m`, whose implementation passes a closure calling `a::target`
+        let mut dynamic = call("a::main", "a::Tr::m", 1);
+        dynamic.kind = CallKind::Dynamic;
+        let mut graph = graph(
+            &["a::main", "a::<a::S as a::Tr>::m", "a::<a::S as a::Tr>::m::{closure#0}", "a::target"],
+            vec![dynamic, call("a::<a::S as a::Tr>::m::{closure#0}", "a::target", 2)],
+        );
+        graph.method_decls.push(crate::testing::function("a::Tr::m"));
+        graph.method_impls.push(method_impls("a::Tr::m", &["a::<a::S as a::Tr>::m"]));
+        let closure = &mut graph.functions[2];
+        closure.kind = FunctionKind::Closure;
+        closure.parent = Some("a::<a::S as a::Tr>::m".to_owned());
+        closure.parent_id = Some(id("a::<a::S as a::Tr>::m"));
+        closure.passed_on = true;
+
+        let chains = query("a::main", "a::target", 1).run(&graph).unwrap();
+        assert_eq!(chains.len(), 1);
+        let kinds: Vec<&str> = chains[0].hops.iter().map(|hop| hop.kind.as_str()).collect();
+        assert_eq!(kinds, ["dynamic", "impl", "closure", "static"]);
+        assert!(chains[0].hops[1].sites.is_empty());
+        assert_eq!(chains[0].hops[3].sites.len(), 1);
+
+        let mut calls_only = query("a::main", "a::target", 1);
+        calls_only.kinds = vec!["static".to_owned(), "dynamic".to_owned()];
+        assert!(calls_only.run(&graph).unwrap().is_empty());
+        // a closure that is not passed on is only reached through the calls to it
+        graph.functions[2].passed_on = false;
+        assert!(query("a::main", "a::target", 1).run(&graph).unwrap().is_empty());
+    }
+
+    #[test]
+    fn no_path() {
+        let mut graph = diamond();
+        graph.calls.retain(|call| call.callee != "a::a");
+        assert!(query("a::b", "a::c", 1).run(&graph).unwrap().is_empty());
+        assert!(query("a::a", "a::a", 1).run(&diamond()).is_err());
+    }
+
+    #[test]
+    fn resolve_names() {
+        let graph = graph(&["a::m::parse", "a::n::parse", "a::m::run"], vec![call("a::m::run", "b::x::parse", 1)]);
+        assert_eq!(resolve(&graph, "a::m::parse"), Ok(vec!["a:a::m::parse"]));
+        assert_eq!(resolve(&graph, "a:a::m::parse"), Ok(vec!["a:a::m::parse"]));
+        assert_eq!(resolve(&graph, "run"), Ok(vec!["a:a::m::run"]));
+        assert_eq!(resolve(&graph, "m::run"), Ok(vec!["a:a::m::run"]));
+        // callees of other crates can be named too
+        assert_eq!(resolve(&graph, "x::parse"), Ok(vec!["b:b::x::parse"]));
+        assert!(resolve(&graph, "parse").unwrap_err().contains("ambiguous"));
+        assert!(resolve(&graph, "missing").is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Whole-program reachability for `--roots`.
+
+use std::collections::{BTreeSet, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Root;
+use crate::graph::{CallGraph, Location};
+use crate::index::CallIndex;
+
+/// The functions reachable from a set of roots.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reachability {
+    /// The functions selected by `--roots`.
+    pub roots: Vec<String>,
+    /// Implementations of traits from other crates, e.g. `Display::fmt` or `Drop::drop`.
+    /// They are called by the dependencies, so they are reachable as well.
+    pub implicit_roots: Vec<String>,
+    /// Functions of the graph reachable from the roots, including the roots.
+    pub reachable: Vec<String>,
+    /// Functions of the graph that are never reached.
+    pub unreachable: Vec<String>,
+    /// Reachable functions of other crates.
+    pub external: Vec<String>,
+}
+
+/// The ids of the functions of the graph selected by `roots`. Roots naming no function
+/// of the graph are reported on stderr.
+pub fn select_roots<'a>(graph: &'a CallGraph, roots: &[Root]) -> BTreeSet<&'a str> {
+    let mut selected = BTreeSet::new();
+    for root in roots {
+        let before = selected.len();
+        for function in &graph.functions {
+            let matches = match root {
+                Root::Main => function.entry,
+                Root::Public => function.public,
+                Root::Path(def_path) => function.def_path == *def_path,
+            };
+            if matches {
+                selected.insert(function.id.as_str());
+            }
+        }
+        if let Root::Path(def_path) = root {
+            if selected.len() == before {
+                eprintln!("warning: root `{}` is not a function of the call graph", def_path);
+            }
+        }
+    }
+    selected
+}
+
+impl Reachability {
+    /// Follows calls, closures and trait method implementations from the `roots`.
+    pub fn new(graph: &CallGraph, roots: &[Root]) -> Reachability {
+        let index = CallIndex::new(graph);
+        let selected = select_roots(graph, roots);
+
+        let local_decls: BTreeSet<&str> = graph.method_decls.iter().map(|decl| decl.id.as_str()).collect();
+        let implicit: BTreeSet<&str> = graph
+            .method_impls
+            .iter()
+            .filter(|method_impls| !local_decls.contains(method_impls.decl_id.as_str()))
+            .flat_map(|method_impls| method_impls.impl_ids.iter().map(String::as_str))
+            .filter(|id| !selected.contains(id))
+            .collect();
+
+        let mut reached: BTreeSet<&str> = BTreeSet::new();
+        let mut stack: Vec<&str> = selected.iter().chain(&implicit).copied().collect();
+        while let Some(id) = stack.pop() {
+            if reached.insert(id) {
+                stack.extend(index.successors(id).filter(|next| !reached.contains(next)));
+            }
+        }
+
+        let local: BTreeSet<&str> = graph.functions.iter().map(|function| function.id.as_str()).collect();
+        let (reachable, unreachable): (Vec<&str>, Vec<&str>) = local.iter().partition(|id| reached.contains(*id));
+        let def_paths = |ids: &mut dyn Iterator<Item = &str>| {
+            let mut def_paths: Vec<String> = ids.map(|id| index.def_path(id).to_owned()).collect();
+            def_paths.sort();
+            def_paths
+        };
+        Reachability {
+            roots: def_paths(&mut selected.iter().copied()),
+            implicit_roots: def_paths(&mut implicit.iter().copied()),
+            reachable: def_paths(&mut reachable.into_iter()),
+            unreachable: def_paths(&mut unreachable.into_iter()),
+            // method declarations are reached through their implementations already
+            external: def_paths(&mut reached.iter().copied().filter(|id| !local.contains(id) && !local_decls.contains(id))),
+        }
+    }
+
+    /// Prints the human readable report used by `--format text`.
+    pub fn dump(&self, graph: &CallGraph) {
+        println!("Roots:");
+        for root in &self.roots {
+            println!("  {}", root);
+        }
+
+        println!("\nImplicit Roots:");
+        for root in &self.implicit_roots {
+            println!("  {}", root);
+        }
+
+        println!("\nReachable Functions ({}):", self.reachable.len());
+        for def_path in &self.reachable {
+            println!("  {}", def_path);
+        }
+
+        let spans: HashMap<&str, &Location> = graph
+            .functions
+            .iter()
+            .filter_map(|function| Some((function.def_path.as_str(), function.span.as_ref()?)))
+            .collect();
+        println!("\nUnreachable Functions ({}):", self.unreachable.len());
+        for def_path in &self.unreachable {
+            match spans.get(def_path.as_str()) {
+                Some(span) => println!("  {}, Span: {}", def_path, span),
+                None => println!("  {}", def_path),
+            }
+        }
+
+        println!("\nExternal Functions ({}):", self.external.len());
+        for def_path in &self.external {
+            println!("  {}", def_path);
+        }
+    }
+}
@@ -0,0 +1,337 @@
+//! Recursion detection with the strongly connected components of the call graph.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{CallGraph, CallKind, CallSite};
+
+/// The recursion cycles of a graph.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recursion {
+    pub cycles: Vec<Cycle>,
+}
+
+/// Functions calling each other, directly or not: a strongly connected component with
+/// more than one function, or a function calling itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cycle {
+    pub functions: Vec<String>,
+    /// Whether the recursion remains with static calls only. Otherwise it depends on
+    /// dynamic dispatch or on a closure being called, and only may happen.
+    pub certain: bool,
+    /// The edges between the functions of the cycle.
+    pub edges: Vec<CycleEdge>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CycleEdge {
+    pub caller: String,
+    pub callee: String,
+    /// The call kind, `impl` from a trait method declaration to an implementation, or
+    /// `closure` from a function to a closure it defines.
+    pub kind: String,
+    pub certain: bool,
+    pub sites: Vec<CallSite>,
+}
+
+impl Recursion {
+    /// Only functions of the graph take part in cycles, calls to other crates are leaves.
+    pub fn new(graph: &CallGraph) -> Recursion {
+        let mut nodes: BTreeMap<&str, usize> = BTreeMap::new();
+        for function in graph.functions.iter().chain(&graph.method_decls) {
+            let next = nodes.len();
+            nodes.entry(&function.id).or_insert(next);
+        }
+
+        // by the ids of the caller and the callee
+        let mut edges: Vec<(&str, &str, CycleEdge)> = Vec::new();
+        for call in &graph.calls {
+            if nodes.contains_key(call.caller_id.as_str()) && nodes.contains_key(call.callee_id.as_str()) {
+                let edge = CycleEdge {
+                    caller: call.caller.clone(),
+                    callee: call.callee.clone(),
+                    kind: call.kind.to_string(),
+                    certain: call.kind != CallKind::Dynamic && !call.may_call,
+                    sites: call.sites.clone(),
+                };
+                edges.push((&call.caller_id, &call.callee_id, edge));
+            }
+        }
+        for method_impls in &graph.method_impls {
+            for (def_path, id) in method_impls.implementations() {
+                // a linked or edited graph may list impls it has no function for
+                if id != method_impls.decl_id
+                    && nodes.contains_key(method_impls.decl_id.as_str())
+                    && nodes.contains_key(id)
+                {
+                    edges.push((&method_impls.decl_id, id, link(&method_impls.decl, def_path, "impl")));
+                }
+            }
+        }
+        // every closure, passed on or not, like `CallIndex`: the cycle is only possible anyway
+        for function in &graph.functions {
+            if let (Some(parent), Some(parent_id)) = (&function.parent, &function.parent_id) {
+                if nodes.contains_key(parent_id.as_str()) {
+                    edges.push((parent_id, &function.id, link(parent, &function.def_path, "closure")));
+                }
+            }
+        }
+
+        let adjacency = |certain_only: bool| {
+            let mut adjacency = vec![Vec::new(); nodes.len()];
+            for (caller, callee, edge) in &edges {
+                if edge.certain || !certain_only {
+                    adjacency[nodes[caller]].push(nodes[callee]);
+                }
+            }
+            adjacency
+        };
+        let all = adjacency(false);
+        let certain = adjacency(true);
+
+        let is_cycle = |scc: &[usize], adjacency: &[Vec<usize>]| scc.len() > 1 || adjacency[scc[0]].contains(&scc[0]);
+        let mut in_certain_cycle = vec![false; nodes.len()];
+        for scc in tarjan(&certain) {
+            if is_cycle(&scc, &certain) {
+                for node in scc {
+                    in_certain_cycle[node] = true;
+                }
+            }
+        }
+
+        let def_paths: HashMap<&str, &str> = graph
+            .functions
+            .iter()
+            .chain(&graph.method_decls)
+            .map(|function| (function.id.as_str(), function.def_path.as_str()))
+            .collect();
+        let names: Vec<&str> = {
+            let mut names = vec![""; nodes.len()];
+            for (name, i) in &nodes {
+                names[*i] = name;
+            }
+            names
+        };
+        let mut cycles: Vec<Cycle> = tarjan(&all)
+            .into_iter()
+            .filter(|scc| is_cycle(scc, &all))
+            .map(|scc| {
+                let component: HashSet<&str> = scc.iter().map(|i| names[*i]).collect();
+                let mut functions: Vec<String> = scc.iter().map(|i| def_paths[names[*i]].to_owned()).collect();
+                functions.sort();
+                let mut cycle_edges: Vec<CycleEdge> = edges
+                    .iter()
+                    .filter(|(caller, callee, _)| component.contains(caller) && component.contains(callee))
+                    .map(|(_, _, edge)| edge.clone())
+                    .collect();
+                cycle_edges.sort_by(|a, b| (&a.caller, &a.callee).cmp(&(&b.caller, &b.callee)));
+                Cycle {
+                    functions,
+                    certain: scc.iter().any(|i| in_certain_cycle[*i]),
+                    edges: cycle_edges,
+                }
+            })
+            .collect();
+        cycles.sort_by(|a, b| (!a.certain, &a.functions).cmp(&(!b.certain, &b.functions)));
+
+        Recursion { cycles }
+    }
+
+    /// Prints the human readable report used by `--format text`.
+    pub fn dump(&self) {
+        let certain = self.cycles.iter().filter(|cycle| cycle.certain).count();
+        println!(
+            "Recursion Cycles: {} certain, {} possible",
+            certain,
+            self.cycles.len() - certain
+        );
+        for cycle in &self.cycles {
+            println!(
+                "\n{} Cycle: {}",
+                if cycle.certain { "Certain" } else { "Possible" },
+                cycle.functions.join(", ")
+            );
+            for edge in &cycle.edges {
+                println!(
+                    "  {} --- {} ({}{})",
+                    edge.caller,
+                    edge.callee,
+                    edge.kind,
+                    if edge.certain { "" } else { ", possible" }
+                );
+                for site in &edge.sites {
+                    if let Some(span) = &site.span {
+                        println!("      at {}", span);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn link(from: &str, to: &str, kind: &str) -> CycleEdge {
+    CycleEdge {
+        caller: from.to_owned(),
+        callee: to.to_owned(),
+        kind: kind.to_owned(),
+        certain: false,
+        sites: Vec::new(),
+    }
+}
+
+/// The strongly connected components of a graph given as adjacency lists, with
+/// Tarjan's algorithm. Iterative, call graphs can be deep.
+pub fn tarjan(adjacency: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    const UNVISITED: usize = usize::MAX;
+    let n = adjacency.len();
+    let mut index = vec![UNVISITED; n];
+    let mut lowlink = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut stack = Vec::new();
+    let mut sccs = Vec::new();
+    let mut next_index = 0;
+
+    for root in 0..n {
+        if index[root] != UNVISITED {
+            continue;
+        }
+        // (node, position in its successors)
+        let mut work = vec![(root, 0)];
+        while let Some((node, pos)) = work.pop() {
+            if pos == 0 {
+                index[node] = next_index;
+                lowlink[node] = next_index;
+                next_index += 1;
+                stack.push(node);
+                on_stack[node] = true;
+            } else {
+                let child = adjacency[node][pos - 1];
+                lowlink[node] = lowlink[node].min(lowlink[child]);
+            }
+
+            let mut recursed = false;
+            for (i, &succ) in adjacency[node].iter().enumerate().skip(pos) {
+                if index[succ] == UNVISITED {
+                    work.push((node, i + 1));
+                    work.push((succ, 0));
+                    recursed = true;
+                    break;
+                } else if on_stack[succ] {
+                    lowlink[node] = lowlink[node].min(index[succ]);
+                }
+            }
+            if recursed {
+                continue;
+            }
+
+            if lowlink[node] == index[node] {
+                let mut scc = Vec::new();
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    scc.push(member);
+                    if member == node {
+                        break;
+                    }
+                }
+                sccs.push(scc);
+            }
+        }
+    }
+    sccs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{call, graph, id, method_impls};
+
+    fn sorted(mut sccs: Vec<Vec<usize>>) -> Vec<Vec<usize>> {
+        for scc in &mut sccs {
+            scc.sort();
+        }
+        sccs.sort();
+        sccs
+    }
+
+    #[test]
+    fn tarjan_three_cycle() {
+        // 0 -> 1 -> 2 -> 0, 2 -> 3, 4 alone
+        let adjacency = vec![vec![1], vec![2], vec![0, 3], vec![], vec![]];
+        assert_eq!(sorted(tarjan(&adjacency)), [vec![0, 1, 2], vec![3], vec![4]]);
+    }
+
+    #[test]
+    fn tarjan_reverse_topological_order() {
+        // 0 -> 1 <-> 2 -> 3: callees come first
+        let adjacency = vec![vec![1], vec![2], vec![1, 3], vec![]];
+        let sccs = tarjan(&adjacency);
+        assert_eq!(sccs.len(), 3);
+        assert_eq!(sccs[0], [3]);
+        assert_eq!(sorted(vec![sccs[1].clone()]), [vec![1, 2]]);
+        assert_eq!(sccs[2], [0]);
+    }
+
+    #[test]
+    fn tarjan_deep_chain() {
+        let n = 100_000;
+        let adjacency: Vec<Vec<usize>> = (0..n).map(|i| if i + 1 < n { vec![i + 1] } else { vec![0] }).collect();
+        let sccs = tarjan(&adjacency);
+        assert_eq!(sccs.len(), 1);
+        assert_eq!(sccs[0].len(), n);
+    }
+
+    #[test]
+    fn certain_and_possible_cycles() {
+        let mut dynamic = call("a::e", "a::d", 6);
+        dynamic.kind = CallKind::Dynamic;
+        let graph = graph(
+            &["a::a", "a::b", "a::c", "a::d", "a::e", "a::f", "a::g"],
+            vec![
+                call("a::a", "a::b", 1),
+                call("a::b", "a::c", 2),
+                call("a::c", "a::a", 3),
+                call("a::c", "a::g", 4),
+                call("a::d", "a::e", 5),
+                dynamic,
+                call("a::f", "a::f", 7),
+            ],
+        );
+        let recursion = Recursion::new(&graph);
+        let cycles: Vec<(bool, Vec<&str>)> = recursion
+            .cycles
+            .iter()
+            .map(|cycle| (cycle.certain, cycle.functions.iter().map(String::as_str).collect()))
+            .collect();
+        assert_eq!(
+            cycles,
+            [(true, vec!["a::a", "a::b", "a::c"]), (true, vec!["a::f"]), (false, vec!["a::d", "a::e"])]
+        );
+        // the call out of the cycle is left out
+        assert_eq!(recursion.cycles[0].edges.len(), 3);
+    }
+
+    #[test]
+    fn cycle_through_a_closure() {
+        let graph = {
+            let mut graph = graph(&["a::f", "a::f::{closure#0}"], vec![call("a::f::{closure#0}", "a::f", 1)]);
+            graph.functions[1].parent = Some("a::f".to_owned());
+            graph.functions[1].parent_id = Some(id("a::f"));
+            graph
+        };
+        let recursion = Recursion::new(&graph);
+        assert_eq!(recursion.cycles.len(), 1);
+        assert!(!recursion.cycles[0].certain);
+    }
+
+    #[test]
+    fn impl_missing_from_the_functions() {
+        let mut graph = graph(&["a::Tr::m", "a::<a::S as a::Tr>::m"], vec![call("a::<a::S as a::Tr>::m", "a::Tr::m", 1)]);
+        let decl = graph.functions.remove(0);
+        graph.method_decls.push(decl);
+        graph.method_impls.push(method_impls("a::Tr::m", &["a::<a::S as a::Tr>::m", "a::<a::T as a::Tr>::m"]));
+        let recursion = Recursion::new(&graph);
+        assert_eq!(recursion.cycles.len(), 1);
+        assert_eq!(recursion.cycles[0].functions, ["a::<a::S as a::Tr>::m", "a::Tr::m"]);
+    }
+}
@@ -0,0 +1,195 @@
+//! Canonical names of functions, shared by all crates and runs.
+//!
+//! A def path is `<crate>::<path>`, where `<crate>` is the rustc name of the crate defining the
+//! item and `<path>` its modules and items from the crate root, e.g. `alloc::vec::from_elem`.
+//! Re-exports are never followed, so `Vec::push` is the same function whether it was called
+//! as `std::vec::Vec::push` or `alloc::vec::Vec::push`.
+//!
+//! The items of an impl are named after the impl header instead of its module, written with
+//! the same rules for every path inside: `alpha::<alpha::Host<S> as core::fmt::Display>::fmt`
+//! for a trait impl, `core::<str>::len` for an inherent impl. The generic parameters of the
+//! self type and the trait are kept, they tell apart `<Foo<u8>>::new` and `<Foo<u16>>::new`.
+//! Closures, anonymous constants and items with the same name in different blocks keep the
+//! disambiguator rustc gives them when it is not zero, e.g. `alpha::main::{closure#0}` or
+//! `alpha::main::helper#1`. Test functions do not, their disambiguator only counts the
+//! constant the test harness adds with their name.
+//!
+//! The id of a function is `<crate>@<version>:<crate id>:<hash>`, with the stable crate id and
+//! the stable hash rustc computes for the def path of the item. The crate id tells apart crates
+//! with the same name, like the lib and the bin of a package. The id is the same in every crate
+//! naming the function, so graphs of different crates and runs can be joined on it. The
+//! version is the same for the crate defining a function and the crates calling it: it comes
+//! from `cargo callgraph` for both, and is left out when unknown, e.g. without `cargo callgraph`,
+//! or ambiguous, for a crate used in several versions which the crate id tells apart.
+
+use std::collections::{HashMap, HashSet};
+
+use rustc_hir::def::DefKind;
+use rustc_hir::def_id::{CrateNum, DefId, DefIndex, LOCAL_CRATE};
+use rustc_hir::definitions::DefPathData;
+use rustc_middle::ty::print::{with_no_trimmed_paths, with_no_visible_paths};
+use rustc_middle::ty::{self, GenericArg, GenericArgKind, Ty, TyCtxt};
+use rustc_span::{sym, Symbol};
+
+pub(crate) struct SymbolNames<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    // the versions of the crates in ids, when known
+    versions: HashMap<CrateNum, String>,
+    // the parents and names of the constants the test harness adds for the local test
+    // functions, which take the disambiguator 0 from them
+    test_consts: HashSet<(DefIndex, Symbol)>,
+}
+
+impl<'tcx> SymbolNames<'tcx> {
+    pub(crate) fn new(tcx: TyCtxt<'tcx>) -> SymbolNames<'tcx> {
+        // `cargo-callgraph` passes the versions of the packages, by crate name
+        let packages: HashMap<String, String> = match std::env::var("CALLGRAPH_CRATE_VERSIONS") {
+            Ok(versions) => serde_json::from_str(&versions).expect("failed to deserialize CALLGRAPH_CRATE_VERSIONS"),
+            Err(_) => HashMap::new(),
+        };
+
+        // the local crate is looked up like its callers do, `CARGO_PKG_VERSION` would name a
+        // crate used in several versions differently
+        let mut versions = HashMap::new();
+        if let Some(version) = packages.get(tcx.crate_name(LOCAL_CRATE).as_str()) {
+            versions.insert(LOCAL_CRATE, version.clone());
+        }
+        for &cnum in tcx.crates(()) {
+            // the standard library has the version of the compiler
+            let in_sysroot = tcx
+                .crate_extern_paths(cnum)
+                .iter()
+                .any(|path| path.starts_with(&tcx.sess.sysroot));
+            let version = if in_sysroot {
+                // e.g. `1.82.0-nightly (60d146580 2024-08-06)`
+                tcx.sess.cfg_version.split_whitespace().next().map(str::to_owned)
+            } else {
+                packages.get(tcx.crate_name(cnum).as_str()).cloned()
+            };
+            if let Some(version) = version {
+                versions.insert(cnum, version);
+            }
+        }
+        let test_consts = tcx
+            .hir_crate_items(())
+            .definitions()
+            .filter(|def_id| tcx.def_kind(*def_id) == DefKind::Const && tcx.has_attr(*def_id, sym::rustc_test_marker))
+            .filter_map(|def_id| {
+                let key = tcx.def_key(def_id);
+                Some((key.parent?, key.disambiguated_data.data.get_opt_name()?))
+            })
+            .collect();
+        SymbolNames { tcx, versions, test_consts }
+    }
+
+    fn version(&self, cnum: CrateNum) -> Option<&str> {
+        self.versions.get(&cnum).map(String::as_str)
+    }
+
+    /// The canonical def path of `def_id`.
+    pub(crate) fn def_path(&self, def_id: DefId) -> String {
+        let tcx = self.tcx;
+        let mut segments = Vec::new();
+        let mut current = def_id;
+        loop {
+            if matches!(tcx.def_kind(current), DefKind::Impl { .. }) {
+                segments.push(self.impl_header(current));
+                break;
+            }
+            let key = tcx.def_key(current);
+            match key.disambiguated_data.data {
+                DefPathData::CrateRoot => break,
+                // like rustc, leave out `extern` blocks and constructors
+                DefPathData::ForeignMod | DefPathData::Ctor => {}
+                _ => {
+                    let data = key.disambiguated_data;
+                    let test_fn = current.is_local()
+                        && data.disambiguator == 1
+                        && key.parent.zip(data.data.get_opt_name()).is_some_and(|parent_name| {
+                            self.test_consts.contains(&parent_name) && tcx.def_kind(current) != DefKind::Const
+                        });
+                    // e.g. `helper#1`, or `{closure#0}` which always has it
+                    let mut segment = String::new();
+                    data.fmt_maybe_verbose(&mut segment, !test_fn).unwrap();
+                    segments.push(segment);
+                }
+            }
+            match key.parent {
+                Some(parent) => current = DefId { krate: current.krate, index: parent },
+                None => break,
+            }
+        }
+        segments.push(tcx.crate_name(def_id.krate).to_string());
+        segments.reverse();
+        segments.join("::")
+    }
+
+    /// The id of `def_id`, see the module documentation.
+    pub(crate) fn id(&self, def_id: DefId) -> String {
+        let hash = self.tcx.def_path_hash(def_id).local_hash().as_u64();
+        let crate_id = self.tcx.stable_crate_id(def_id.krate).as_u64();
+        let name = self.tcx.crate_name(def_id.krate);
+        match self.version(def_id.krate) {
+            Some(version) => format!("{}@{}:{:016x}:{:016x}", name, version, crate_id, hash),
+            None => format!("{}:{:016x}:{:016x}", name, crate_id, hash),
+        }
+    }
+
+    // `<Self as Trait>` or `<Self>`, with canonical paths
+    fn impl_header(&self, impl_id: DefId) -> String {
+        let self_ty = self.ty_name(self.tcx.type_of(impl_id).instantiate_identity());
+        match self.tcx.impl_trait_ref(impl_id) {
+            Some(trait_ref) => {
+                let trait_ref = trait_ref.instantiate_identity();
+                // the first argument is the self type
+                let trait_path = self.with_args(self.def_path(trait_ref.def_id), &trait_ref.args[1..]);
+                format!("<{} as {}>", self_ty, trait_path)
+            }
+            None => format!("<{}>", self_ty),
+        }
+    }
+
+    // Types with canonical paths. Lifetimes are left out, they never tell two impls apart.
+    fn ty_name(&self, ty: Ty<'tcx>) -> String {
+        match *ty.kind() {
+            ty::Adt(adt, args) => self.with_args(self.def_path(adt.did()), args),
+            ty::Foreign(def_id) => self.def_path(def_id),
+            ty::Ref(_, ty, mutbl) => format!("&{}{}", mutbl.prefix_str(), self.ty_name(ty)),
+            ty::RawPtr(ty, mutbl) => format!("*{} {}", mutbl.ptr_str(), self.ty_name(ty)),
+            ty::Slice(ty) => format!("[{}]", self.ty_name(ty)),
+            ty::Array(ty, len) => format!("[{}; {}]", self.ty_name(ty), len),
+            ty::Tuple(tys) if tys.len() == 1 => format!("({},)", self.ty_name(tys[0])),
+            ty::Tuple(tys) => {
+                let tys: Vec<String> = tys.iter().map(|ty| self.ty_name(ty)).collect();
+                format!("({})", tys.join(", "))
+            }
+            ty::Dynamic(predicates, _, _) => {
+                let mut traits: Vec<String> = Vec::new();
+                if let Some(principal) = predicates.principal() {
+                    let principal = principal.skip_binder();
+                    traits.push(self.with_args(self.def_path(principal.def_id), principal.args));
+                }
+                traits.extend(predicates.auto_traits().map(|def_id| self.def_path(def_id)));
+                format!("dyn {}", traits.join(" + "))
+            }
+            // primitives, generic parameters, function pointers
+            _ => with_no_visible_paths!(with_no_trimmed_paths!(ty.to_string())),
+        }
+    }
+
+    fn with_args(&self, path: String, args: &[GenericArg<'tcx>]) -> String {
+        let args: Vec<String> = args
+            .iter()
+            .filter_map(|arg| match arg.unpack() {
+                GenericArgKind::Type(ty) => Some(self.ty_name(ty)),
+                GenericArgKind::Const(ct) => Some(ct.to_string()),
+                GenericArgKind::Lifetime(_) => None,
+            })
+            .collect();
+        if args.is_empty() {
+            path
+        } else {
+            format!("{}<{}>", path, args.join(", "))
+        }
+    }
+}
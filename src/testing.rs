@@ -0,0 +1,91 @@
+//! Call graphs built by hand for the unit tests.
+
+use crate::graph::{
+    Call, CallGraph, CallKind, CallSite, ControlPath, Function, FunctionKind, Location, MethodImpls, SCHEMA_VERSION,
+};
+
+/// A public free function of the crate its def path starts with.
+pub(crate) fn function(def_path: &str) -> Function {
+    let krate = def_path.split("::").next().unwrap().to_owned();
+    let module = match def_path.rsplit_once("::") {
+        Some((module, _)) => module.to_owned(),
+        None => krate.clone(),
+    };
+    Function {
+        def_path: def_path.to_owned(),
+        id: id(def_path),
+        krate,
+        module,
+        span: None,
+        target: None,
+        kind: FunctionKind::Fn,
+        parent: None,
+        parent_id: None,
+        passed_on: false,
+        public: true,
+        entry: false,
+        panic_sites: Vec::new(),
+        unsafe_fn: false,
+        unsafe_blocks: Vec::new(),
+        foreign_calls: Vec::new(),
+    }
+}
+
+/// The id of a function of these tests, made of its def path.
+pub(crate) fn id(def_path: &str) -> String {
+    format!("{}:{}", def_path.split("::").next().unwrap(), def_path)
+}
+
+/// A static call with a single site on the normal path, at `line` of `lib.rs`.
+pub(crate) fn call(caller: &str, callee: &str, line: usize) -> Call {
+    let site = CallSite {
+        span: Some(location(line)),
+        constraint_depth: 0,
+        condition: None,
+        condition_text: None,
+        loops: Vec::new(),
+        path: ControlPath::Normal,
+    };
+    Call {
+        kind: CallKind::Static,
+        caller: caller.to_owned(),
+        caller_id: id(caller),
+        caller_crate: caller.split("::").next().unwrap().to_owned(),
+        callee: callee.to_owned(),
+        callee_id: id(callee),
+        callee_crate: callee.split("::").next().unwrap().to_owned(),
+        callee_span: None,
+        constraint_depth: 0,
+        loop_depth: 0,
+        may_call: false,
+        count: 1,
+        sites: vec![site],
+    }
+}
+
+/// The implementations `impls` of the trait method `decl`.
+pub(crate) fn method_impls(decl: &str, impls: &[&str]) -> MethodImpls {
+    MethodImpls {
+        decl: decl.to_owned(),
+        decl_id: id(decl),
+        impls: impls.iter().map(|def_path| def_path.to_string()).collect(),
+        impl_ids: impls.iter().map(|def_path| id(def_path)).collect(),
+    }
+}
+
+pub(crate) fn location(line: usize) -> Location {
+    Location { file: "lib.rs".to_owned(), line, column: 5, end_line: line, end_column: 10 }
+}
+
+/// The graph of the functions with these def paths and `calls`.
+pub(crate) fn graph(functions: &[&str], calls: Vec<Call>) -> CallGraph {
+    CallGraph {
+        schema_version: SCHEMA_VERSION,
+        krate: "a".to_owned(),
+        crates: Vec::new(),
+        functions: functions.iter().map(|def_path| function(def_path)).collect(),
+        method_decls: Vec::new(),
+        method_impls: Vec::new(),
+        calls,
+    }
+}
@@ -0,0 +1,179 @@
+//! Unsafe code and FFI reachability for `--unsafe`, to scope security audits.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::graph::{CallGraph, Function, Location};
+use crate::index::{chain, hops, shortest_chains, Hop};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Unsafety {
+    /// The functions with unsafe code or FFI calls of their own.
+    pub unsafe_functions: Vec<UnsafeFunction>,
+    /// The safe functions of the public API reaching one of them.
+    pub exposed: Vec<ExposedFunction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnsafeFunction {
+    pub def_path: String,
+    pub unsafe_fn: bool,
+    pub unsafe_blocks: Vec<Location>,
+    pub foreign_calls: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExposedFunction {
+    pub def_path: String,
+    /// The shortest call chain to a function of `unsafe_functions`, empty if it is one.
+    pub chain: Vec<Hop>,
+    /// The unsafe function at the end of the chain.
+    pub reaches: String,
+}
+
+impl Unsafety {
+    /// Calls are followed like for `--panics`, into the implementations of trait methods
+    /// and the closures a function defines and passes to a call.
+    pub fn new(graph: &CallGraph) -> Unsafety {
+        let mut unsafe_functions: Vec<&Function> = graph.functions.iter().filter(|function| is_unsafe(function)).collect();
+        unsafe_functions.sort_by(|a, b| a.def_path.cmp(&b.def_path).then(a.id.cmp(&b.id)));
+
+        let hops = hops(graph);
+        let targets = unsafe_functions.iter().map(|function| (function.id.as_str(), None));
+        let first_hops = shortest_chains(&hops, targets);
+        let by_id: HashMap<&str, &Function> =
+            graph.functions.iter().map(|function| (function.id.as_str(), function)).collect();
+
+        let mut exposed: Vec<&Function> = graph
+            .functions
+            .iter()
+            .filter(|function| function.public && !function.unsafe_fn)
+            .filter(|function| first_hops.contains_key(function.id.as_str()))
+            .collect();
+        exposed.sort_by(|a, b| a.def_path.cmp(&b.def_path).then(a.id.cmp(&b.id)));
+        let exposed = exposed
+            .into_iter()
+            .map(|function| {
+                let (chain, end) = chain(&first_hops, &function.id);
+                ExposedFunction { def_path: function.def_path.clone(), chain, reaches: by_id[end].def_path.clone() }
+            })
+            .collect();
+        let unsafe_functions = unsafe_functions
+            .into_iter()
+            .map(|function| UnsafeFunction {
+                def_path: function.def_path.clone(),
+                unsafe_fn: function.unsafe_fn,
+                unsafe_blocks: function.unsafe_blocks.clone(),
+                foreign_calls: function.foreign_calls.clone(),
+            })
+            .collect();
+
+        Unsafety { unsafe_functions, exposed }
+    }
+
+    /// Prints the human readable report used by `--format text`.
+    pub fn dump(&self) {
+        println!("Unsafe Functions ({}):", self.unsafe_functions.len());
+        for function in &self.unsafe_functions {
+            let mut details = Vec::new();
+            if function.unsafe_fn {
+                details.push("unsafe fn".to_owned());
+            }
+            if !function.unsafe_blocks.is_empty() {
+                details.push(format!("Unsafe Blocks: {}", function.unsafe_blocks.len()));
+            }
+            if !function.foreign_calls.is_empty() {
+                details.push(format!("Foreign Calls: {}", function.foreign_calls.join(", ")));
+            }
+            println!("  {} ({})", function.def_path, details.join(", "));
+            for span in &function.unsafe_blocks {
+                println!("      unsafe at {}", span);
+            }
+        }
+
+        println!("\nSafe Public Functions Reaching Unsafe Code ({}):", self.exposed.len());
+        for function in &self.exposed {
+            println!("  {} -> {}", function.def_path, function.reaches);
+            for hop in &function.chain {
+                match &hop.span {
+                    Some(span) => println!("      {} --- {} ({}) at {}", hop.caller, hop.callee, hop.kind, span),
+                    None => println!("      {} --- {} ({})", hop.caller, hop.callee, hop.kind),
+                }
+            }
+        }
+    }
+}
+
+fn is_unsafe(function: &Function) -> bool {
+    function.unsafe_fn || !function.unsafe_blocks.is_empty() || !function.foreign_calls.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::{Call, CallKind};
+    use crate::testing::{call, function, graph, location, method_impls};
+
+    // `a::raw` is an `unsafe fn`, `a::ffi` calls C and `a::blocks` has an `unsafe` block
+    fn audited(calls: Vec<Call>) -> CallGraph {
+        let mut graph = graph(&["a::api", "a::blocks", "a::ffi", "a::internal", "a::raw", "a::safe"], calls);
+        graph.functions[1].unsafe_blocks.push(location(2));
+        graph.functions[2].foreign_calls.push("a::ffi::strlen".to_owned());
+        graph.functions[2].public = false;
+        graph.functions[3].public = false;
+        graph.functions[4].unsafe_fn = true;
+        graph
+    }
+
+    fn exposed(unsafety: &Unsafety) -> Vec<(&str, &str)> {
+        unsafety.exposed.iter().map(|function| (function.def_path.as_str(), function.reaches.as_str())).collect()
+    }
+
+    #[test]
+    fn unsafe_fns_blocks_and_foreign_calls() {
+        let unsafety = Unsafety::new(&audited(Vec::new()));
+        let functions: Vec<(&str, bool, usize, &[String])> = unsafety
+            .unsafe_functions
+            .iter()
+            .map(|function| {
+                (function.def_path.as_str(), function.unsafe_fn, function.unsafe_blocks.len(), function.foreign_calls.as_slice())
+            })
+            .collect();
+        assert_eq!(
+            functions,
+            [
+                ("a::blocks", false, 1, &[][..]),
+                ("a::ffi", false, 0, &["a::ffi::strlen".to_owned()][..]),
+                ("a::raw", true, 0, &[][..]),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_safe_public_functions_are_exposed() {
+        let unsafety = Unsafety::new(&audited(vec![
+            call("a::api", "a::ffi", 1),
+            call("a::internal", "a::raw", 2),
+            call("a::raw", "a::blocks", 3),
+        ]));
+        // `a::ffi` and `a::internal` are not public, `a::raw` is an `unsafe fn` itself
+        assert_eq!(exposed(&unsafety), [("a::api", "a::ffi"), ("a::blocks", "a::blocks")]);
+        assert_eq!(unsafety.exposed[0].chain.len(), 1);
+        assert!(unsafety.exposed[1].chain.is_empty());
+    }
+
+    #[test]
+    fn trait_methods_lead_to_their_implementations() {
+        let mut dynamic = call("a::api", "a::Tr::m", 1);
+        dynamic.kind = CallKind::Dynamic;
+        let mut graph = audited(vec![dynamic]);
+        graph.method_decls.push(function("a::Tr::m"));
+        graph.method_impls.push(method_impls("a::Tr::m", &["a::blocks"]));
+
+        let unsafety = Unsafety::new(&graph);
+        assert_eq!(exposed(&unsafety), [("a::api", "a::blocks"), ("a::blocks", "a::blocks")]);
+        let kinds: Vec<&str> = unsafety.exposed[0].chain.iter().map(|hop| hop.kind.as_str()).collect();
+        assert_eq!(kinds, ["dynamic", "impl"]);
+    }
+}
@@ -1,20 +1,19 @@
-use rustc_hir::def::Res;
 use rustc_hir::HirId;
-use rustc_hir::def_id::DefId;
-use rustc_middle::ty::Ty;
-use rustc_middle::ty::TyCtxt;
+use rustc_hir::def::{DefKind, Res};
+use rustc_hir::def_id::{DefId, LOCAL_CRATE};
+use rustc_middle::ty::{self, TyCtxt};
 use rustc_middle::ty::ParamEnvAnd;
+use rustc_middle::ty::adjustment::{Adjust, PointerCoercion};
 use std::collections::{HashMap, HashSet};
-use std::path;
-use std::string;
-use rustc_hir::intravisit;
+use std::ops::Not;
+use rustc_hir::intravisit::{self, Visitor};
 use rustc_middle::hir::nested_filter;
 use rustc_span::Span;
-use std::cmp::PartialEq;
-use std::hash::{Hash, Hasher};
 use rustc_hir::LangItem;
-use cargo_metadata::{MetadataCommand, Package};
-use toml;
+
+use crate::config::DynResolution;
+use crate::graph::{self, CallGraph, CallKind, Location};
+use crate::symbol::SymbolNames;
 
 
 macro_rules! skip_generated_code {
@@ -26,16 +25,26 @@ macro_rules! skip_generated_code {
 }
 
 // Backup self.cur_fn, set cur_fn to id, continue to walk the AST by executing
-// $walk, then restore self.cur_fn.
+// $walk, then restore self.cur_fn. The conditions, loops and control path of the
+// definition do not apply to the body.
 macro_rules! push_walk_pop {
     ($this: expr, $id: expr, $walk: expr) => {{
         let prev_fn = $this.cur_fn;
+        let prev_conditions = std::mem::take(&mut $this.conditions);
+        let prev_loops = std::mem::take(&mut $this.loops);
+        let prev_path = std::mem::take(&mut $this.path);
+        let prev_generated_path = std::mem::take(&mut $this.generated_path);
         $this.cur_fn = Some($id);
         $walk;
         $this.cur_fn = prev_fn;
+        $this.conditions = prev_conditions;
+        $this.loops = prev_loops;
+        $this.path = prev_path;
+        $this.generated_path = prev_generated_path;
     }};
 }
 
+// A single call site. All sites with the same caller and callee form one edge.
 #[derive( Debug, Clone)]
 struct Call {
     // the call expression
@@ -43,40 +52,36 @@ struct Call {
     call_expr_span: Span,
     // possible enclosing function
     caller: Option<DefId>,
-    caller_span: Option<Span>,
     // call target
     callee: DefId,
     callee_span: Span,
     caller_path: String,
     callee_path: String,
     constraint_depth: usize,
-}
-
-impl Eq for Call {}
-
-impl Hash for Call {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.caller.hash(state);
-        self.callee.hash(state);
-    }
-}
-
-// 手动实现 PartialEq 只比较 caller 和 callee
-impl PartialEq for Call {
-    fn eq(&self, other: &Self) -> bool {
-        self.caller == other.caller && self.callee_path == other.callee_path
-    }
+    // what must hold for the call to run
+    condition: Option<graph::Condition>,
+    // the enclosing loops, outermost first
+    loops: Vec<graph::Loop>,
+    // whether the call only runs when failing
+    path: graph::ControlPath,
 }
 
 impl Call {
-    fn should_insert(&self, new_depth: usize) -> bool {
-        new_depth < self.constraint_depth
+    fn key(&self) -> CallKey {
+        (self.caller, self.callee_path.clone())
     }
 }
 
+// (caller, callee path) identifying an edge
+type CallKey = (Option<DefId>, String);
+
 pub struct CallgraphVisitor<'tcx> {
     // type context
     tcx: TyCtxt<'tcx>,
+    // name of the local crate
+    crate_name: String,
+    // canonical def paths and ids
+    names: SymbolNames<'tcx>,
 
     // free functions
     functions: HashSet<(DefId, Span)>,
@@ -85,129 +90,315 @@ pub struct CallgraphVisitor<'tcx> {
     // map decls to impls
     method_impls: HashMap<DefId, Vec<DefId>>,
 
-    // static calls
-    static_calls: HashSet<Call>,
+    // static calls, every site of each edge
+    static_calls: HashMap<CallKey, Vec<Call>>,
     // dynamic calls
-    dynamic_calls: HashSet<Call>,
+    dynamic_calls: HashMap<CallKey, Vec<Call>>,
     //non local calls
-    non_local_calls: HashSet<Call>,
+    non_local_calls: HashMap<CallKey, Vec<Call>>,
+
+    // closures and the function defining them
+    closure_parents: HashMap<DefId, DefId>,
+    // closures and functions that may be stored in a local, for calls through the local
+    local_flows: HashMap<HirId, Vec<DefId>>,
+    // closures passed to a call by the function defining them
+    passed_closures: HashSet<DefId>,
+
+    // how dynamic calls are expanded to the implementations they may call
+    dyn_resolution: DynResolution,
+    // traits and the types coerced to `dyn Trait`, see `type_key`
+    dyn_types: HashMap<DefId, HashSet<String>>,
 
     // tracks the current function we're in during AST walk
     cur_fn: Option<DefId>,
 
     // 新增字段来跟踪约束层数
     constraint_depth: usize,
+    // the conditions guarding the current expression, innermost last
+    conditions: Vec<graph::Condition>,
+    // the regions of each function guarded by a condition, for the calls found in the MIR
+    guards: HashMap<DefId, Vec<(Span, graph::Condition)>>,
+    // the loops around the current expression, innermost last
+    loops: Vec<graph::Loop>,
+    // the regions of each function repeated by a loop, for the calls found in the MIR
+    loop_regions: HashMap<DefId, Vec<(Span, graph::Loop)>>,
+    // the control path of the current expression
+    path: graph::ControlPath,
+    // the regions of each function only running when failing, for the calls found in the MIR
+    path_regions: HashMap<DefId, Vec<(Span, graph::ControlPath)>>,
+    // the control path comes from code generated by a macro, e.g. the failing branch of
+    // `assert!`, whose region does not cover the arguments written in the source
+    generated_path: bool,
+    // operations that may panic without a call, e.g. indexing, by function
+    panic_sites: HashMap<DefId, Vec<(graph::PanicKind, Span)>>,
+    // `unsafe` blocks by function
+    unsafe_blocks: HashMap<DefId, Vec<Span>>,
+    // the functions of `extern` blocks called by each function
+    foreign_calls: HashMap<DefId, Vec<DefId>>,
 
     enter_if: bool,
 
 }
 
 impl<'tcx> CallgraphVisitor<'tcx> {
-    pub fn new(tcx: &TyCtxt<'tcx>) -> CallgraphVisitor<'tcx> {
+    pub fn new(tcx: TyCtxt<'tcx>, dyn_resolution: DynResolution) -> CallgraphVisitor<'tcx> {
         CallgraphVisitor {
-            tcx: *tcx,
+            tcx,
+            crate_name: tcx.crate_name(LOCAL_CRATE).to_string(),
+            names: SymbolNames::new(tcx),
             functions: HashSet::new(),
             method_decls: HashSet::new(),
             method_impls: HashMap::new(),
-            static_calls: HashSet::new(),
-            dynamic_calls: HashSet::new(),
-            non_local_calls: HashSet::new(),
+            static_calls: HashMap::new(),
+            dynamic_calls: HashMap::new(),
+            non_local_calls: HashMap::new(),
+            closure_parents: HashMap::new(),
+            local_flows: HashMap::new(),
+            passed_closures: HashSet::new(),
+            dyn_resolution,
+            dyn_types: HashMap::new(),
             cur_fn: None,
             constraint_depth: 0,
+            conditions: Vec::new(),
+            guards: HashMap::new(),
+            loops: Vec::new(),
+            loop_regions: HashMap::new(),
+            path: graph::ControlPath::Normal,
+            path_regions: HashMap::new(),
+            generated_path: false,
+            panic_sites: HashMap::new(),
+            unsafe_blocks: HashMap::new(),
+            foreign_calls: HashMap::new(),
             enter_if: false,
         }
     }
 
-    pub fn dump(&self) {
-        println!("Functions:");
-        for (def_id, span) in &self.functions {
-            let function_name = self.tcx.def_path_str(*def_id);
-            println!("  Function: {}, Span: {:?}", function_name, span);
-        }
+    /// Converts the collected calls into the serializable `CallGraph`.
+    pub fn graph(&self) -> CallGraph {
+        let tcx = self.tcx;
+        let local_crate = self.crate_name.clone();
+        let entry_fn = tcx.entry_fn(()).map(|(def_id, _)| def_id);
+        let function = |def_id: DefId, span: Span| graph::Function {
+            def_path: self.get_full_path(Some(def_id)),
+            id: self.names.id(def_id),
+            krate: tcx.crate_name(def_id.krate).to_string(),
+            module: self.module_path(def_id),
+            span: Location::from_span(tcx, span),
+            target: None,
+            kind: if tcx.is_closure_like(def_id) {
+                graph::FunctionKind::Closure
+            } else {
+                graph::FunctionKind::Fn
+            },
+            parent: self.closure_parents.get(&def_id).map(|parent| self.get_full_path(Some(*parent))),
+            parent_id: self.closure_parents.get(&def_id).map(|parent| self.names.id(*parent)),
+            passed_on: self.passed_closures.contains(&def_id),
+            public: def_id
+                .as_local()
+                .is_some_and(|id| tcx.effective_visibilities(()).is_exported(id)),
+            entry: entry_fn == Some(def_id),
+            panic_sites: self
+                .panic_sites
+                .get(&def_id)
+                .into_iter()
+                .flatten()
+                .map(|(kind, span)| graph::PanicSite { kind: *kind, span: Location::from_span(tcx, span.source_callsite()) })
+                .collect(),
+            unsafe_fn: matches!(tcx.def_kind(def_id), DefKind::Fn | DefKind::AssocFn)
+                && tcx.fn_sig(def_id).skip_binder().safety() == rustc_hir::Safety::Unsafe,
+            unsafe_blocks: self
+                .unsafe_blocks
+                .get(&def_id)
+                .into_iter()
+                .flatten()
+                .filter_map(|span| Location::from_span(tcx, span.source_callsite()))
+                .collect(),
+            foreign_calls: self
+                .foreign_calls
+                .get(&def_id)
+                .into_iter()
+                .flatten()
+                .map(|callee| self.get_full_path(Some(*callee)))
+                .collect(),
+        };
 
-        println!("\nMethod Declarations:");
-        for def_id in &self.method_decls {
-            let method_name = self.tcx.def_path_str(*def_id);
-            println!("  Method Declaration: {}", method_name);
-        }
+        let mut functions: Vec<_> = self.functions
+            .iter()
+            .map(|(def_id, span)| function(*def_id, *span))
+            .collect();
+        functions.sort_by(|a, b| a.def_path.cmp(&b.def_path));
+
+        let mut method_decls: Vec<_> = self.method_decls
+            .iter()
+            .map(|def_id| function(*def_id, tcx.def_span(*def_id)))
+            .collect();
+        method_decls.sort_by(|a, b| a.def_path.cmp(&b.def_path));
+
+        let mut method_impls: Vec<_> = self.method_impls
+            .iter()
+            .map(|(decl_id, impl_ids)| graph::MethodImpls {
+                decl: self.get_full_path(Some(*decl_id)),
+                decl_id: self.names.id(*decl_id),
+                impls: impl_ids.iter().map(|id| self.get_full_path(Some(*id))).collect(),
+                impl_ids: impl_ids.iter().map(|id| self.names.id(*id)).collect(),
+            })
+            .collect();
+        method_impls.sort_by(|a, b| a.decl.cmp(&b.decl));
+
+        let edge = |kind: CallKind, sites: &[Call], may_call: Option<DefId>| {
+            let first = &sites[0];
+            let mut call_sites: Vec<_> = sites
+                .iter()
+                .map(|site| graph::CallSite {
+                    // point calls generated by macros at the macro invocation
+                    span: Location::from_span(tcx, site.call_expr_span.source_callsite()),
+                    constraint_depth: site.constraint_depth,
+                    condition: site.condition.clone(),
+                    condition_text: site.condition.as_ref().map(|condition| condition.to_string()),
+                    loops: site.loops.clone(),
+                    path: site.path,
+                })
+                .collect();
+            call_sites.sort_by_key(|site| site.span.as_ref().map(|span| (span.line, span.column)));
+            // may-call edges go to an implementation instead of the called declaration
+            let (callee, callee_path, callee_span) = match may_call {
+                Some(callee) => (callee, self.get_full_path(Some(callee)), tcx.def_span(callee)),
+                None => (first.callee, first.callee_path.clone(), first.callee_span),
+            };
+            graph::Call {
+                kind,
+                caller: first.caller_path.clone(),
+                caller_id: first.caller.map(|caller| self.names.id(caller)).unwrap_or_default(),
+                caller_crate: local_crate.clone(),
+                callee: callee_path,
+                callee_id: self.names.id(callee),
+                callee_crate: tcx.crate_name(callee.krate).to_string(),
+                callee_span: Location::from_span(tcx, callee_span),
+                constraint_depth: sites.iter().map(|site| site.constraint_depth).min().unwrap_or(0),
+                loop_depth: sites.iter().map(|site| site.loops.len()).max().unwrap_or(0),
+                may_call: may_call.is_some(),
+                count: call_sites.len(),
+                sites: call_sites,
+            }
+        };
 
-        println!("\nMethod Implementations:");
-        for (decl_id, impl_ids) in &self.method_impls {
-            let decl_name = self.tcx.def_path_str(*decl_id);
-            println!("  Method Implementation for {}: {:?}", decl_name, impl_ids);
+        let calls = [
+            (CallKind::Static, &self.static_calls),
+            (CallKind::Dynamic, &self.dynamic_calls),
+            (CallKind::NonLocal, &self.non_local_calls),
+        ];
+        let mut calls: Vec<_> = calls
+            .into_iter()
+            .flat_map(|(kind, calls)| calls.values().map(move |sites| (kind, sites)))
+            .map(|(kind, sites)| edge(kind, sites, None))
+            .collect();
+        for sites in self.dynamic_calls.values() {
+            for callee in self.dispatch_targets(sites[0].callee) {
+                calls.push(edge(CallKind::Dynamic, sites, Some(callee)));
+            }
         }
-
-        println!("\nStatic Calls:");
-        for call in &self.static_calls {
-            // let caller_str = match call.caller {
-            //     Some(caller) => self.tcx.def_path_str(caller),
-            //     None => "Unknown Caller".to_string(),
-            // };
-            // let callee_str = self.tcx.def_path_str(call.callee);
-
-            println!("{} --- {} (Constraint Depth: {})", call.caller_path, call.callee_path, call.constraint_depth);
+        calls.sort_by(|a, b| (&a.caller, &a.callee).cmp(&(&b.caller, &b.callee)));
+
+        CallGraph {
+            schema_version: graph::SCHEMA_VERSION,
+            crates: vec![graph::CrateIdentity {
+                name: local_crate.clone(),
+                version: std::env::var("CARGO_PKG_VERSION").ok(),
+                disambiguator: format!("{:016x}", tcx.stable_crate_id(LOCAL_CRATE).as_u64()),
+                package_id: cargo_package_id(),
+                working_dir: tcx.sess.opts.working_dir.local_path().map(|dir| dir.display().to_string()),
+            }],
+            krate: local_crate,
+            functions,
+            method_decls,
+            method_impls,
+            calls,
         }
+    }
 
-        println!("\nDynamic Calls:");
-        for call in &self.dynamic_calls {
-            // let caller_str = match call.caller {
-            //     Some(caller) => self.tcx.def_path_str(caller),
-            //     None => "Unknown Caller".to_string(),
-            // };
-            // let callee_str = self.tcx.def_path_str(call.callee);
+    /// Forgets the calls and panic sites found so far, the functions, trait methods and
+    /// `unsafe` blocks are kept.
+    pub(crate) fn clear_calls(&mut self) {
+        self.static_calls.clear();
+        self.dynamic_calls.clear();
+        self.non_local_calls.clear();
+        self.panic_sites.clear();
+        self.foreign_calls.clear();
+    }
 
-            println!("{} --- {} (Constraint Depth: {})", call.caller_path, call.callee_path, call.constraint_depth);
+    /// Records an operation of `caller` that may panic, once per span.
+    pub(crate) fn record_panic_site(&mut self, caller: DefId, kind: graph::PanicKind, span: Span) {
+        let sites = self.panic_sites.entry(caller).or_default();
+        if !sites.contains(&(kind, span)) {
+            sites.push((kind, span));
         }
+    }
 
-        println!("\nNon Local Calls:");
-        for call in &self.non_local_calls {
-            // let caller_str = match call.caller {
-            //     Some(caller) => self.tcx.def_path_str(caller),
-            //     None => "Unknown Caller".to_string(),
-            // };
+    /// Records a call found by another backend. `call_expr` and `span` identify the
+    /// call site, `span` alone when the backend has no expression for it.
+    pub(crate) fn record_call(
+        &mut self,
+        kind: CallKind,
+        caller: DefId,
+        callee: DefId,
+        call_expr: HirId,
+        span: Span,
+        constraint_depth: usize,
+    ) {
+        let new_call = Call {
+            call_expr,
+            call_expr_span: span,
+            caller: Some(caller),
+            callee,
+            callee_span: self.tcx.hir().span_if_local(callee).unwrap_or_default(),
+            caller_path: self.get_full_path(Some(caller)),
+            callee_path: self.get_full_path(Some(callee)),
+            constraint_depth,
+            condition: self.condition_at(caller, span),
+            loops: self.loops_at(caller, span),
+            path: self.path_at(caller, callee, span),
+        };
+        self.handle_call(new_call, kind);
+    }
 
-            println!("{} --- {} (Constraint Depth: {})", call.caller_path, call.callee_path, call.constraint_depth);
+    // A call site of the current function, in the current conditions, loops and control path.
+    fn new_call(&self, call_expr: HirId, call_expr_span: Span, callee: DefId, callee_span: Span) -> Call {
+        Call {
+            call_expr,
+            call_expr_span,
+            caller: self.cur_fn,
+            callee,
+            callee_span,
+            caller_path: self.get_full_path(self.cur_fn),
+            callee_path: self.get_full_path(Some(callee)),
+            constraint_depth: self.constraint_depth,
+            condition: self.current_condition(),
+            loops: self.loops.clone(),
+            path: self.path,
         }
     }
 
-    fn handle_call(&mut self, new_call: Call, call_type: String) {
-        if call_type == "static"{
-            if let Some(existing_call) = self.static_calls.get(&new_call).cloned() {
-                if existing_call.should_insert(self.constraint_depth) {
-                    self.static_calls.remove(&existing_call);
-                    self.static_calls.insert(new_call);
-                    //println!("Updated call with new constraint depth: {}", self.constraint_depth);
-                }
-            } else {
-                self.static_calls.insert(new_call);
-                //println!("Inserted new call with constraint depth: {}", self.constraint_depth);
-            }
-        }
-        else if call_type == "dynamic"{
-            if let Some(existing_call) = self.dynamic_calls.get(&new_call).cloned() {
-                if existing_call.should_insert(self.constraint_depth) {
-                    self.dynamic_calls.remove(&existing_call);
-                    self.dynamic_calls.insert(new_call);
-                    //println!("Updated call with new constraint depth: {}", self.constraint_depth);
-                }
-            } else {
-                self.dynamic_calls.insert(new_call);
-                //println!("Inserted new call with constraint depth: {}", self.constraint_depth);
+    fn handle_call(&mut self, new_call: Call, kind: CallKind) {
+        if let (Some(caller), true) = (new_call.caller, self.tcx.is_foreign_item(new_call.callee)) {
+            let callees = self.foreign_calls.entry(caller).or_default();
+            if !callees.contains(&new_call.callee) {
+                callees.push(new_call.callee);
             }
         }
-        else{
-            if let Some(existing_call) = self.non_local_calls.get(&new_call).cloned() {
-                if existing_call.should_insert(self.constraint_depth) {
-                    self.non_local_calls.remove(&existing_call);
-                    self.non_local_calls.insert(new_call);
-                    //println!("Updated call with new constraint depth: {}", self.constraint_depth);
-                }
-            } else {
-                self.non_local_calls.insert(new_call);
-                //println!("Inserted new call with constraint depth: {}", self.constraint_depth);
-            }
+        let calls = match kind {
+            CallKind::Static => &mut self.static_calls,
+            CallKind::Dynamic => &mut self.dynamic_calls,
+            CallKind::NonLocal => &mut self.non_local_calls,
+        };
+
+        let sites = calls.entry(new_call.key()).or_default();
+        // a call expression is only recorded once, even if it is walked again
+        if !sites
+            .iter()
+            .any(|site| site.call_expr == new_call.call_expr && site.call_expr_span == new_call.call_expr_span)
+        {
+            sites.push(new_call);
         }
-        
     }
 
     fn process_method_call(&mut self, hir_id: HirId, segment: &&rustc_hir::PathSegment<'_>, expr: &'tcx rustc_hir::Expr){
@@ -230,100 +421,45 @@ impl<'tcx> CallgraphVisitor<'tcx> {
                                 let res_def_id = inst.def_id();
                                 // println!("caller: {:?}", self.cur_fn);
                                 // println!("def_id: {:?}, get_path: {:#?}", res_def_id, self.tcx.def_path_str(res_def_id));
-                                // println!("the complete path is {:#?}", self.get_full_path(self.tcx, res_def_id));
+                                // println!("the complete path is {:#?}", self.get_full_path(res_def_id));
                                 match self.tcx.hir().get_if_local(res_def_id) {
                                     Some(rustc_hir::Node::TraitItem(rustc_hir::TraitItem { span, .. })) => {
                                         // dynamic calls resolve only to the trait method decl
-                                        let new_call = Call {
-                                            call_expr: hir_id,
-                                            call_expr_span: expr.span,
-                                            caller: self.cur_fn,
-                                            caller_span: None,
-                                            callee: res_def_id,
-                                            callee_span: *span,
-                                            // callee_path: self.tcx.def_path_str(res_def_id),
-                                            caller_path: self.get_full_path(self.tcx, self.cur_fn),
-                                            callee_path: self.get_full_path(self.tcx, Some(res_def_id)),
-                                            constraint_depth: self.constraint_depth,
-                                        };
-                                        self.handle_call(new_call, "dynamic".to_string());
+                                        let new_call = self.new_call(hir_id, expr.span, res_def_id, *span);
+                                        self.handle_call(new_call, CallKind::Dynamic);
                                     }
                                     Some(rustc_hir::Node::ImplItem(rustc_hir::ImplItem { span, .. })) |
                                     Some(rustc_hir::Node::Item(rustc_hir::Item { span, .. })) |
                                     Some(rustc_hir::Node::ForeignItem(rustc_hir::ForeignItem { span, .. })) => {
                                         // calls for which the receiver's type can be resolved
-                                        let new_call = Call {
-                                            call_expr: hir_id,
-                                            call_expr_span: expr.span,
-                                            caller: self.cur_fn,
-                                            caller_span: None,
-                                            callee: res_def_id,
-                                            callee_span: *span,
-                                            // callee_path: self.tcx.def_path_str(res_def_id),
-                                            caller_path: self.get_full_path(self.tcx, self.cur_fn),
-                                            callee_path: self.get_full_path(self.tcx, Some(res_def_id)),
-                                            constraint_depth: self.constraint_depth,
-                                        };
-
-                                        self.handle_call(new_call, "static".to_string());
+                                        let new_call = self.new_call(hir_id, expr.span, res_def_id, *span);
+
+                                        self.handle_call(new_call, CallKind::Static);
                                     }
                                     None => {
-                                        let new_call = Call {
-                                            call_expr: hir_id,
-                                            call_expr_span: expr.span,
-                                            caller: self.cur_fn,
-                                            caller_span: None,
-                                            callee: res_def_id,
-                                            callee_span: Span::default(),
-                                            // callee_path: self.tcx.def_path_str(res_def_id),
-                                            caller_path: self.get_full_path(self.tcx, self.cur_fn),
-                                            callee_path: self.get_full_path(self.tcx, Some(res_def_id)),
-                                            constraint_depth: self.constraint_depth,
-                                        };
-
-                                        self.handle_call(new_call, "non_local".to_string());
+                                        let new_call = self.new_call(hir_id, expr.span, res_def_id, Span::default());
+
+                                        self.handle_call(new_call, CallKind::NonLocal);
                                     },
-                                    _ => todo!()
+                                    Some(_) => {
+                                        // e.g. `f.call((x,))` resolving to the body of the closure `f`
+                                        let new_call = self.new_call(hir_id, expr.span, res_def_id, self.tcx.def_span(res_def_id));
+                                        self.handle_call(new_call, CallKind::Static);
+                                    }
                                 };
                             },
                             Ok(None) | Err(_) => {
                                 // 无法解析为具体实例，可能是动态分发的调用
-                                let new_call = Call {
-                                    call_expr: hir_id,
-                                    call_expr_span: expr.span,
-                                    caller: self.cur_fn,
-                                    caller_span: None,
-                                    callee: def_id,
-                                    callee_span: expr.span,
-                                    // callee_path: self.tcx.def_path_str(def_id),
-                                    caller_path: self.get_full_path(self.tcx, self.cur_fn),
-                                    callee_path: self.get_full_path(self.tcx, Some(def_id)),
-                                    constraint_depth: self.constraint_depth,
-                                };
-            
-                                println!("new dynamic call: {:#?}", new_call);
-                                self.handle_call(new_call, "dynamic".to_string());
+                                let new_call = self.new_call(hir_id, expr.span, def_id, expr.span);
+                                self.handle_call(new_call, CallKind::Dynamic);
                             }
                         }
                     }
                     }
                     None => {
                         // 动态分发：无法直接解析具体的实现
-                        let new_call = Call {
-                            call_expr: hir_id,
-                            call_expr_span: expr.span,
-                            caller: self.cur_fn,
-                            caller_span: None,
-                            callee: segment.res.def_id(),
-                            callee_span: expr.span,
-                            // callee_path: self.tcx.def_path_str(segment.res.def_id()),
-                            caller_path: self.get_full_path(self.tcx, self.cur_fn),
-                            callee_path: self.get_full_path(self.tcx, Some(segment.res.def_id())),
-                            constraint_depth: self.constraint_depth,
-                        };
-            
-                        println!("new dynamic call: {:#?}", new_call);
-                        self.handle_call(new_call, "dynamic".to_string());
+                        let new_call = self.new_call(hir_id, expr.span, segment.res.def_id(), expr.span);
+                        self.handle_call(new_call, CallKind::Dynamic);
                     }
                 }
             
@@ -334,57 +470,51 @@ impl<'tcx> CallgraphVisitor<'tcx> {
         match qpath {
             rustc_hir::QPath::Resolved(_, p) => {
                 // println!("Resolved path: {:?}", p); // 打印解析后的路径信息
+                if let Res::Local(_) = p.res {
+                    // calling a closure or function pointer stored in a local
+                    if let rustc_hir::ExprKind::Call(callee, _) = expr.kind {
+                        self.process_indirect_call(hir_id, callee, expr);
+                    }
+                }
                 if let rustc_hir::def::Res::Def(_, def_id) = p.res {
-                    let new_call = Call {
-                        call_expr: hir_id,
-                        call_expr_span: expr.span,
-                        caller: self.cur_fn,
-                        caller_span: None,
-                        callee: def_id,
-                        callee_span: p.span,
-                        // callee_path: self.tcx.def_path_str(def_id),
-                        caller_path: self.get_full_path(self.tcx, self.cur_fn),
-                        callee_path: self.get_full_path(self.tcx, Some(def_id)),
-                        constraint_depth: self.constraint_depth,
-                    };
+                    let new_call = self.new_call(hir_id, expr.span, def_id, p.span);
 
                     //println!("resolved new call {:?}", new_call);
         
                     // 检查是否已经存在相同的调用（只比较 caller 和 callee）
-                    self.handle_call(new_call, "static".to_string());
+                    self.handle_call(new_call, CallKind::Static);
                 }
             }
             rustc_hir::QPath::TypeRelative(ty, path_segment) => {   
                 // println!("TypeRelative path: {:?}", ty);
-                if let rustc_hir::TyKind::Path(ref qpath) = ty.kind {
-                    if let rustc_hir::QPath::Resolved(_, path) = qpath {
-                        if let rustc_hir::def::Res::Def(_, def_id) = path.res {
-                            // Convert DefId and Ident to strings for printing
-                            // let def_id_str = self.tcx.def_path_str(def_id);
-                            let def_id_str = self.get_full_path(self.tcx, Some(def_id));
-                            let ident_str = path_segment.ident.to_string();
-                            let callee_path_output = def_id_str + "::" + &ident_str;
-                            let new_call = Call {
-                                call_expr: hir_id,
-                                call_expr_span: expr.span,
-                                caller: self.cur_fn,
-                                caller_span: None,
-                                callee: def_id,
-                                callee_span: path_segment.ident.span,   //error span
-                                caller_path: self.get_full_path(self.tcx, self.cur_fn),
-                                callee_path: callee_path_output.clone(),
-                                constraint_depth: self.constraint_depth,
-                            };
-                            // println!("Typeratived new call {:?}", new_call);
+                // `Type::method`: typeck knows which associated function is called
+                let resolved = match expr.kind {
+                    rustc_hir::ExprKind::Call(callee, _) => {
+                        self.tcx.typeck(hir_id.owner).type_dependent_def_id(callee.hir_id)
+                    }
+                    _ => None,
+                };
+                if let Some(def_id) = resolved {
+                    let new_call = self.new_call(hir_id, expr.span, def_id, path_segment.ident.span);
+                    self.handle_call(new_call, CallKind::Static);
+                } else if let rustc_hir::TyKind::Path(rustc_hir::QPath::Resolved(_, path)) = ty.kind {
+                    if let rustc_hir::def::Res::Def(_, def_id) = path.res {
+                        // Convert DefId and Ident to strings for printing
+                        // let def_id_str = self.tcx.def_path_str(def_id);
+                        let def_id_str = self.get_full_path(Some(def_id));
+                        let ident_str = path_segment.ident.to_string();
+                        let callee_path_output = def_id_str + "::" + &ident_str;
+                        let mut new_call = self.new_call(hir_id, expr.span, def_id, path_segment.ident.span);
+                        new_call.callee_path = callee_path_output;
+                        // println!("Typeratived new call {:?}", new_call);
                     
-                            self.handle_call(new_call, "static".to_string());
-                        }
+                        self.handle_call(new_call, CallKind::Static);
                     }
                 }
             }
                 
                 
-            rustc_hir::QPath::LangItem(_, span) => {
+            rustc_hir::QPath::LangItem(_, _span) => {
                 //println!("LangItem path: {:?}", span); // 打印语言项路径信息
             }
         }
@@ -393,68 +523,525 @@ impl<'tcx> CallgraphVisitor<'tcx> {
     }
 
     
-    fn get_full_path(&mut self, tcx: TyCtxt<'_>, def_id: Option<DefId>) -> String {
-        // If def_id is None, return empty string
-        let def_id = match def_id {
-            Some(id) => id,
-            None => return String::new(),
+    // Calls through closures, function pointers and `Fn` trait objects or parameters.
+    fn process_indirect_call(&mut self, hir_id: HirId, callee: &'tcx rustc_hir::Expr, expr: &'tcx rustc_hir::Expr) {
+        let typeck_tables = self.tcx.typeck(hir_id.owner);
+        let callee_ty = typeck_tables.expr_ty_adjusted(callee).peel_refs();
+
+        // the type of closures and fn items tells exactly what is called
+        let (targets, kind) = match callee_ty.kind() {
+            ty::Closure(def_id, _) | ty::FnDef(def_id, _) => (vec![*def_id], CallKind::Static),
+            _ => (self.flow_targets(callee), CallKind::Dynamic),
         };
-        
-        // 获取 def_path_str
-        let def_path_str = tcx.def_path_str(def_id);
-        
-        // 获取当前 crate 名称
-        let current_crate_name = self.get_current_crate_name().unwrap_or_else(|| "unknown".to_string());
-        
-        //println!("current_crate_name:{}", current_crate_name);
-        
-        // 如果是本地 crate 定义的函数
-        if def_id.is_local() {
-            //println!("the function {} is local", def_path_str);
-            // 如果 def_path_str 已经包含 crate 名称，直接返回
-            if def_path_str.starts_with(&current_crate_name) {
-                return def_path_str;
-            }
-            // 否则，在前面加上当前 crate 名称
-            format!("{}::{}", current_crate_name, def_path_str)
+
+        let targets = if targets.is_empty() {
+            // nothing flows here that we know of, fall back to the `Fn*` trait method like
+            // dynamic method calls resolve to the trait method decl
+            typeck_tables.type_dependent_def_id(hir_id).into_iter().collect()
         } else {
-            // 非本地函数，直接返回完整路径
-            def_path_str
+            targets
+        };
+
+        for def_id in targets {
+            let new_call = self.new_call(hir_id, expr.span, def_id, callee.span);
+            self.handle_call(new_call, kind);
         }
     }
 
-    fn get_current_crate_name(&self) -> Option<String> {
-        // 获取当前工作目录
-        
-        let current_dir = std::env::current_dir().ok()?;
-        //println!("Current directory: {}", current_dir.display());  // 打印当前目录
-    
-        // 构建 Cargo.toml 的路径
-        let cargo_toml_path = current_dir.join("Cargo.toml");
-    
-        // 确保文件存在
-        if !cargo_toml_path.exists() {
+    // Closures and functions the value of `expr` may be, following locals within the body.
+    fn flow_targets(&self, expr: &rustc_hir::Expr) -> Vec<DefId> {
+        match expr.kind {
+            rustc_hir::ExprKind::Closure(closure) => vec![closure.def_id.to_def_id()],
+            rustc_hir::ExprKind::Path(ref qpath) => {
+                match self.tcx.typeck(expr.hir_id.owner).qpath_res(qpath, expr.hir_id) {
+                    Res::Local(local) => self.local_flows.get(&local).cloned().unwrap_or_default(),
+                    Res::Def(DefKind::Fn | DefKind::AssocFn, def_id) => vec![def_id],
+                    _ => vec![],
+                }
+            }
+            rustc_hir::ExprKind::AddrOf(_, _, inner)
+            | rustc_hir::ExprKind::Cast(inner, _)
+            | rustc_hir::ExprKind::DropTemps(inner) => self.flow_targets(inner),
+            rustc_hir::ExprKind::Block(block, _) => {
+                block.expr.map(|tail| self.flow_targets(tail)).unwrap_or_default()
+            }
+            rustc_hir::ExprKind::If(_, then, else_ex) => {
+                let mut targets = self.flow_targets(then);
+                targets.extend(else_ex.map(|else_ex| self.flow_targets(else_ex)).unwrap_or_default());
+                targets
+            }
+            rustc_hir::ExprKind::Match(_, arms, _) => {
+                arms.iter().flat_map(|arm| self.flow_targets(arm.body)).collect()
+            }
+            // `Box::new(|| ..)` for `Box<dyn Fn()>`
+            rustc_hir::ExprKind::Call(_, [arg])
+                if self.tcx.typeck(expr.hir_id.owner).expr_ty(expr).is_box() =>
+            {
+                self.flow_targets(arg)
+            }
+            _ => vec![],
+        }
+    }
+
+    // Remembers the closures passed as `args` of the call `call`, the callee may call them.
+    fn record_passed_closures(&mut self, call: &rustc_hir::Expr, args: &[rustc_hir::Expr]) {
+        // `Box::new(|| ..)` only stores the closure, see `flow_targets`
+        if self.tcx.typeck(call.hir_id.owner).expr_ty(call).is_box() {
+            return;
+        }
+        for arg in args {
+            let closures = self.flow_targets(arg).into_iter().filter(|def_id| self.tcx.is_closure_like(*def_id));
+            self.passed_closures.extend(closures.collect::<Vec<_>>());
+        }
+    }
+
+    // Remembers the closures and functions that may be stored in the local `local`.
+    fn add_local_flow(&mut self, local: HirId, value: &rustc_hir::Expr) {
+        let targets = self.flow_targets(value);
+        if !targets.is_empty() {
+            let flows = self.local_flows.entry(local).or_default();
+            for target in targets {
+                if !flows.contains(&target) {
+                    flows.push(target);
+                }
+            }
+        }
+    }
+
+    // The implementations a dynamic call of the trait method `decl` may dispatch to.
+    fn dispatch_targets(&self, decl: DefId) -> Vec<DefId> {
+        let Some(impls) = self.method_impls.get(&decl) else {
+            return vec![];
+        };
+        let impls = impls.iter().copied().filter(|id| *id != decl);
+        match self.dyn_resolution {
+            DynResolution::None => vec![],
+            DynResolution::Cha => impls.collect(),
+            DynResolution::Rta => {
+                let Some(trait_id) = self.tcx.trait_of_item(decl) else {
+                    return vec![];
+                };
+                // types coerced to the trait or to one of its subtraits
+                let types: HashSet<&String> = self.dyn_types
+                    .iter()
+                    .filter(|(dyn_trait, _)| self.tcx.supertrait_def_ids(**dyn_trait).any(|id| id == trait_id))
+                    .flat_map(|(_, types)| types)
+                    .collect();
+                impls
+                    .filter(|id| {
+                        let Some(impl_id) = self.tcx.impl_of_method(*id) else {
+                            return false;
+                        };
+                        let self_ty = self.tcx.type_of(impl_id).instantiate_identity();
+                        // blanket impls may be used by any type
+                        matches!(self_ty.kind(), ty::Param(_)) || types.contains(&self.type_key(self_ty))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    // Records the `dyn Trait` the type of `expr` is coerced to, for `--dyn-resolution rta`.
+    fn record_dyn_coercions(&mut self, expr: &rustc_hir::Expr) {
+        if self.dyn_resolution != DynResolution::Rta {
+            return;
+        }
+        let typeck = self.tcx.typeck(expr.hir_id.owner);
+        let mut source = typeck.expr_ty(expr);
+        let mut coercions = vec![];
+        for adjustment in typeck.expr_adjustments(expr) {
+            if let Adjust::Pointer(PointerCoercion::Unsize) = adjustment.kind {
+                coercions.push((source, adjustment.target));
+            }
+            source = adjustment.target;
+        }
+        if let rustc_hir::ExprKind::Cast(inner, _) = expr.kind {
+            coercions.push((typeck.expr_ty_adjusted(inner), typeck.expr_ty(expr)));
+        }
+
+        for (source, target) in coercions {
+            let (Some(source), Some(target)) = (pointee(source), pointee(target)) else {
+                continue;
+            };
+            if let ty::Dynamic(predicates, _, _) = target.kind() {
+                if let Some(trait_id) = predicates.principal_def_id() {
+                    let key = self.type_key(source);
+                    self.dyn_types.entry(trait_id).or_default().insert(key);
+                }
+            }
+        }
+    }
+
+    // The conjunction of the conditions guarding the current expression.
+    fn current_condition(&self) -> Option<graph::Condition> {
+        graph::Condition::all(self.conditions.iter().cloned())
+    }
+
+    // The conditions guarding `span` in `caller`, for calls found in the MIR.
+    fn condition_at(&self, caller: DefId, span: Span) -> Option<graph::Condition> {
+        let guards = self.guards.get(&caller)?;
+        let span = span.source_callsite();
+        graph::Condition::all(
+            guards
+                .iter()
+                .filter(|(region, _)| region.contains(span))
+                .map(|(_, condition)| condition.clone()),
+        )
+    }
+
+    // The loops around `span` in `caller`, for calls found in the MIR.
+    fn loops_at(&self, caller: DefId, span: Span) -> Vec<graph::Loop> {
+        let span = span.source_callsite();
+        let Some(regions) = self.loop_regions.get(&caller) else {
+            return Vec::new();
+        };
+        // outer loops are walked first
+        regions
+            .iter()
+            .filter(|(region, _)| region.contains(span))
+            .map(|(_, repeated)| repeated.clone())
+            .collect()
+    }
+
+    // Walks the body of a loop. For a `for` loop the region is the body written in the
+    // source: the desugared calls of the loop header share the span of the iterator,
+    // which is only evaluated once.
+    fn walk_loop(&mut self, expr: &'tcx rustc_hir::Expr, body: &'tcx rustc_hir::Block, source: rustc_hir::LoopSource) {
+        let (kind, region) = match source {
+            rustc_hir::LoopSource::Loop => (graph::LoopKind::Loop, expr.span),
+            rustc_hir::LoopSource::While => (graph::LoopKind::While, expr.span),
+            rustc_hir::LoopSource::ForLoop => (graph::LoopKind::For, Self::for_loop_body(body).unwrap_or(expr.span)),
+        };
+        let repeated = graph::Loop { kind, span: Location::from_span(self.tcx, expr.span) };
+        if let Some(caller) = self.cur_fn {
+            let regions = self.loop_regions.entry(caller).or_default();
+            if !regions.iter().any(|(r, _)| *r == region) {
+                regions.push((region, repeated.clone()));
+            }
+        }
+        self.loops.push(repeated);
+        self.visit_block(body);
+        self.loops.pop();
+    }
+
+    // `loop { match next(&mut iter) { None => break, Some(pat) => body } }`
+    fn for_loop_body(block: &rustc_hir::Block) -> Option<Span> {
+        let [stmt] = block.stmts else {
             return None;
+        };
+        match stmt.kind {
+            rustc_hir::StmtKind::Expr(rustc_hir::Expr {
+                kind: rustc_hir::ExprKind::Match(_, [.., some_arm], rustc_hir::MatchSource::ForLoopDesugar),
+                ..
+            }) => Some(some_arm.body.span),
+            _ => None,
         }
-    
-        // 读取 Cargo.toml 文件
-        let cargo_toml_content = std::fs::read_to_string(&cargo_toml_path).ok()?;
-    
-        // 解析 TOML 内容
-        let value: toml::Value = toml::from_str(&cargo_toml_content).ok()?;
-    
-        // 获取当前 crate 的名称
-        if let Some(package) = value.get("package") {
-            if let Some(name) = package.get("name") {
-                return name.as_str().map(|s| s.to_string());
+    }
+
+    // The control path of a call to `callee` at `span` in `caller`, for calls found in the MIR.
+    fn path_at(&self, caller: DefId, callee: DefId, span: Span) -> graph::ControlPath {
+        if Self::panic_call_site(span).is_some() && !Self::is_debug_assertion(span) {
+            return graph::ControlPath::Panic;
+        }
+        // `?` calls `Try::branch` and `FromResidual::from_residual` with the same span
+        if span.desugaring_kind() == Some(rustc_span::DesugaringKind::QuestionMark)
+            && self.tcx.opt_item_name(callee).is_some_and(|name| name.as_str() == "from_residual")
+        {
+            return graph::ControlPath::Error;
+        }
+        self.path_regions
+            .get(&caller)
+            .into_iter()
+            .flatten()
+            .filter(|(region, _)| Self::covers(*region, span))
+            .map(|(_, path)| *path)
+            .max()
+            .unwrap_or_default()
+    }
+
+    // Walks `walk` on `path` if it is less common than the current one, see `ControlPath`.
+    fn on_path(&mut self, region: Option<Span>, path: graph::ControlPath, walk: impl FnOnce(&mut Self)) {
+        if path <= self.path {
+            walk(self);
+            return;
+        }
+        if let Some(region) = region {
+            self.record_path_region(region, path);
+        }
+        let prev_path = std::mem::replace(&mut self.path, path);
+        let prev_generated = std::mem::replace(&mut self.generated_path, region.map_or(true, |region| region.from_expansion()));
+        walk(self);
+        self.path = prev_path;
+        self.generated_path = prev_generated;
+    }
+
+    fn record_path_region(&mut self, region: Span, path: graph::ControlPath) {
+        if let Some(caller) = self.cur_fn {
+            let regions = self.path_regions.entry(caller).or_default();
+            if !regions.contains(&(region, path)) {
+                regions.push((region, path));
             }
         }
-        
-        None
     }
-    
-    
-    
+
+    // Whether the MIR call at `span` is in `region`. The regions generated by a macro, e.g.
+    // the branches of `assert!`, may span the whole invocation: they only cover the code
+    // the macro generated, not its arguments.
+    fn covers(region: Span, span: Span) -> bool {
+        if region.from_expansion() {
+            region.contains(span) && span.ctxt().outer_expn().is_descendant_of(region.ctxt().outer_expn())
+        } else {
+            region.contains(span) || region.contains(span.source_callsite())
+        }
+    }
+
+    // Walks a branch, on the path it ends in if it returns an error or panics.
+    fn walk_branch(&mut self, body: &'tcx rustc_hir::Expr, path: graph::ControlPath, walk: impl FnOnce(&mut Self)) {
+        let path = path.max(self.diverges_to(body));
+        self.on_path(Some(body.span), path, walk);
+    }
+
+    // Whether `expr` ends by returning an error or panicking.
+    fn diverges_to(&self, expr: &rustc_hir::Expr) -> graph::ControlPath {
+        // like for the constraint depth, debug assertions do not count
+        if Self::is_debug_assertion(expr.span) {
+            return graph::ControlPath::Normal;
+        }
+        if Self::panic_call_site(expr.span).is_some() {
+            return graph::ControlPath::Panic;
+        }
+        match expr.kind {
+            rustc_hir::ExprKind::Block(block, _) => self.block_diverges_to(block),
+            rustc_hir::ExprKind::Ret(Some(value)) if self.is_err(value) => graph::ControlPath::Error,
+            // e.g. `assert_failed` of `assert_eq!`
+            rustc_hir::ExprKind::Call(
+                rustc_hir::Expr { kind: rustc_hir::ExprKind::Path(rustc_hir::QPath::Resolved(_, path)), .. },
+                _,
+            ) => match path.res {
+                Res::Def(DefKind::Fn, def_id) if self.is_panicking_fn(def_id) => graph::ControlPath::Panic,
+                _ => graph::ControlPath::Normal,
+            },
+            _ => graph::ControlPath::Normal,
+        }
+    }
+
+    fn block_diverges_to(&self, block: &rustc_hir::Block) -> graph::ControlPath {
+        let tail = block.expr.or_else(|| match block.stmts.last()?.kind {
+            rustc_hir::StmtKind::Expr(expr) | rustc_hir::StmtKind::Semi(expr) => Some(expr),
+            _ => None,
+        });
+        tail.map_or(graph::ControlPath::Normal, |tail| self.diverges_to(tail))
+    }
+
+    // `Err(..)`
+    fn is_err(&self, expr: &rustc_hir::Expr) -> bool {
+        match expr.kind {
+            rustc_hir::ExprKind::Call(
+                rustc_hir::Expr { kind: rustc_hir::ExprKind::Path(rustc_hir::QPath::Resolved(_, path)), .. },
+                _,
+            ) => self.is_err_ctor(path.res),
+            _ => false,
+        }
+    }
+
+    // The path of the code run when `pat` matched: `Err(..)` only matches errors.
+    fn pattern_path(&self, pat: &rustc_hir::Pat) -> graph::ControlPath {
+        match pat.kind {
+            rustc_hir::PatKind::TupleStruct(rustc_hir::QPath::Resolved(_, path), ..) if self.is_err_ctor(path.res) => {
+                graph::ControlPath::Error
+            }
+            _ => graph::ControlPath::Normal,
+        }
+    }
+
+    fn is_err_ctor(&self, res: Res) -> bool {
+        match res {
+            Res::Def(DefKind::Ctor(..), ctor) => self.tcx.lang_items().get(LangItem::ResultErr) == Some(self.tcx.parent(ctor)),
+            _ => false,
+        }
+    }
+
+    fn is_panicking_fn(&self, def_id: DefId) -> bool {
+        let def_path = self.names.def_path(def_id);
+        def_path.starts_with("core::panicking::") || def_path.starts_with("std::panicking::")
+    }
+
+    // The invocation of the panicking macro `span` comes from, if any.
+    fn panic_call_site(span: Span) -> Option<Span> {
+        span.macro_backtrace().find_map(|expn| match expn.kind {
+            rustc_span::ExpnKind::Macro(rustc_span::MacroKind::Bang, name)
+                if matches!(name.as_str(), "todo" | "unimplemented")
+                    || name.as_str().starts_with("panic")
+                    || name.as_str().starts_with("unreachable") =>
+            {
+                Some(expn.call_site)
+            }
+            _ => None,
+        })
+    }
+
+    // Walks `walk` with `condition` guarding `region`.
+    fn guarded(&mut self, region: Span, condition: Option<graph::Condition>, walk: impl FnOnce(&mut Self)) {
+        let Some(condition) = condition else {
+            walk(self);
+            return;
+        };
+        self.push_condition(region, condition);
+        walk(self);
+        self.conditions.pop();
+    }
+
+    // Guards the rest of the walk with `condition`, and the calls the MIR has in `region`.
+    fn push_condition(&mut self, region: Span, condition: graph::Condition) {
+        // the branches of code generated by a macro, e.g. `assert!`, may span the whole
+        // invocation, arguments included
+        if let (Some(caller), false) = (self.cur_fn, region.from_expansion()) {
+            let guards = self.guards.entry(caller).or_default();
+            if !guards.iter().any(|(r, c)| *r == region && *c == condition) {
+                guards.push((region, condition.clone()));
+            }
+        }
+        self.conditions.push(condition);
+    }
+
+    // The right operand of `&&` and `||` only runs for some values of the left one.
+    fn visit_short_circuit(&mut self, op: rustc_hir::BinOpKind, lhs: &'tcx rustc_hir::Expr, rhs: &'tcx rustc_hir::Expr) {
+        let condition = match op {
+            rustc_hir::BinOpKind::And => Some(self.formula(lhs)),
+            rustc_hir::BinOpKind::Or => Some(self.formula(lhs).not()),
+            _ => None,
+        };
+        self.guarded(rhs.span, condition, |this| this.visit_expr(rhs));
+    }
+
+    // The boolean formula of a condition expression.
+    fn formula(&self, expr: &rustc_hir::Expr) -> graph::Condition {
+        match expr.kind {
+            rustc_hir::ExprKind::DropTemps(inner) => self.formula(inner),
+            rustc_hir::ExprKind::Binary(op, lhs, rhs) if op.node == rustc_hir::BinOpKind::And => {
+                graph::Condition::all([self.formula(lhs), self.formula(rhs)]).unwrap()
+            }
+            rustc_hir::ExprKind::Binary(op, lhs, rhs) if op.node == rustc_hir::BinOpKind::Or => {
+                let mut operands = Vec::new();
+                for operand in [self.formula(lhs), self.formula(rhs)] {
+                    match operand {
+                        graph::Condition::Or { operands: nested } => operands.extend(nested),
+                        operand => operands.push(operand),
+                    }
+                }
+                graph::Condition::Or { operands }
+            }
+            rustc_hir::ExprKind::Unary(rustc_hir::UnOp::Not, operand) => self.formula(operand).not(),
+            rustc_hir::ExprKind::Let(let_expr) => self.matches(let_expr.pat, let_expr.init, let_expr.span),
+            _ => graph::Condition::Expr {
+                text: self.source_text(expr.span, expr.hir_id),
+                span: Location::from_span(self.tcx, expr.span),
+            },
+        }
+    }
+
+    // `scrutinee` matches `pat`.
+    fn matches(&self, pat: &rustc_hir::Pat, scrutinee: &rustc_hir::Expr, span: Span) -> graph::Condition {
+        graph::Condition::Matches {
+            scrutinee: self.source_text(scrutinee.span, scrutinee.hir_id),
+            pattern: self.source_text(pat.span, pat.hir_id),
+            span: Location::from_span(self.tcx, span),
+        }
+    }
+
+    // The source of a node, or the HIR pretty printed for code generated by macros.
+    fn source_text(&self, span: Span, hir_id: HirId) -> String {
+        if !span.from_expansion() {
+            if let Ok(snippet) = self.tcx.sess.source_map().span_to_snippet(span) {
+                return snippet;
+            }
+        }
+        rustc_hir_pretty::id_to_string(&self.tcx.hir(), hir_id)
+    }
+
+    // The arms of a `match` written in the source, each guarded by its pattern and guard
+    // and by the previous arms not matching.
+    fn walk_match_arms(&mut self, scrutinee: &'tcx rustc_hir::Expr, arms: &'tcx [rustc_hir::Arm<'tcx>]) {
+        let mut previous: Vec<graph::Condition> = Vec::new();
+        for arm in arms {
+            self.visit_pat(arm.pat);
+            // `_` and bindings always match
+            let irrefutable = matches!(arm.pat.kind, rustc_hir::PatKind::Wild | rustc_hir::PatKind::Binding(.., None));
+            let pattern = (!irrefutable).then(|| self.matches(arm.pat, scrutinee, arm.pat.span));
+            let guard = arm.guard.map(|guard| self.formula(guard));
+
+            let not_previous = previous.iter().map(|condition| condition.clone().not());
+            let arm_matches = graph::Condition::all(not_previous.chain(pattern.clone()));
+            let path = self.pattern_path(arm.pat);
+            if let Some(guard) = arm.guard {
+                self.guarded(guard.span, arm_matches.clone(), |this| {
+                    this.on_path(Some(guard.span), path, |this| this.visit_expr(guard))
+                });
+            }
+            let arm_condition = graph::Condition::all(arm_matches.into_iter().chain(guard.clone()));
+            self.guarded(arm.body.span, arm_condition, |this| {
+                this.walk_branch(arm.body, path, |this| this.visit_expr(arm.body))
+            });
+
+            match graph::Condition::all(pattern.into_iter().chain(guard)) {
+                Some(condition) => previous.push(condition),
+                // the later arms are unreachable
+                None => break,
+            }
+        }
+    }
+
+    // Whether `span` comes from a `debug_assert!` family macro, which only checks in debug builds.
+    fn is_debug_assertion(span: Span) -> bool {
+        span.macro_backtrace().any(|expn| match expn.kind {
+            rustc_span::ExpnKind::Macro(_, name) => name.as_str().starts_with("debug_assert"),
+            _ => false,
+        })
+    }
+
+    // Identifies a type regardless of its generic arguments, e.g. `Wrapper<i32>` and
+    // the self type `Wrapper<T>` of an impl.
+    fn type_key(&self, ty: ty::Ty<'_>) -> String {
+        match ty.kind() {
+            ty::Adt(adt, _) => self.tcx.def_path_str(adt.did()),
+            _ => ty.to_string(),
+        }
+    }
+
+    fn get_full_path(&self, def_id: Option<DefId>) -> String {
+        // If def_id is None, return empty string
+        let def_id = match def_id {
+            Some(id) => id,
+            None => return String::new(),
+        };
+
+        // 本地和外部的函数用同一种路径，见 `symbol` 模块
+        self.names.def_path(def_id)
+    }
+
+    // path of the module containing the local `def_id`, consistent with `get_full_path`
+    fn module_path(&self, def_id: DefId) -> String {
+        let module = self.tcx.parent_module_from_def_id(def_id.expect_local()).to_def_id();
+        self.get_full_path(Some(module))
+    }
+}
+
+// The Cargo package id of the crate being compiled. `cargo-callgraph` passes the ids of the
+// workspace members in `CALLGRAPH_PACKAGES`, by manifest directory; Cargo sets
+// `CARGO_MANIFEST_DIR` for every crate it compiles.
+fn cargo_package_id() -> Option<String> {
+    let manifest_dir = std::env::var("CARGO_MANIFEST_DIR").ok()?;
+    if let Ok(packages) = std::env::var("CALLGRAPH_PACKAGES") {
+        let packages: HashMap<String, String> =
+            serde_json::from_str(&packages).expect("failed to deserialize CALLGRAPH_PACKAGES");
+        if let Some(id) = packages.get(&manifest_dir) {
+            return Some(id.clone());
+        }
+    }
+    // without `cargo-callgraph`, fall back to a package id spec
+    let name = std::env::var("CARGO_PKG_NAME").ok()?;
+    let version = std::env::var("CARGO_PKG_VERSION").ok()?;
+    Some(format!("{}@{}", name, version))
 }
 
 
@@ -474,6 +1061,25 @@ impl<'tcx> intravisit::Visitor<'tcx> for CallgraphVisitor<'tcx> {
 
     fn visit_expr(&mut self, expr: &'tcx rustc_hir::Expr) {
         // skip_generated_code!(expr.span);
+        // debug assertions only check in debug builds, their panics stay on the normal path
+        if self.path != graph::ControlPath::Panic && !Self::is_debug_assertion(expr.span) {
+            if let Some(call_site) = Self::panic_call_site(expr.span) {
+                // a panic generated by another macro, e.g. `assert!`, may be invoked with the
+                // span of the whole assertion
+                let region = (!call_site.from_expansion()).then_some(call_site);
+                self.on_path(region, graph::ControlPath::Panic, |this| this.visit_expr(expr));
+                return;
+            }
+        }
+        if self.generated_path && !expr.span.from_expansion() {
+            // e.g. the message of `assert!`
+            self.record_path_region(expr.span, self.path);
+            self.generated_path = false;
+            self.visit_expr(expr);
+            self.generated_path = true;
+            return;
+        }
+        self.record_dyn_coercions(expr);
         let old_depth = self.constraint_depth; // 保存当前深度
         let hir_id = expr.hir_id;
         let mut flag = true;
@@ -481,28 +1087,35 @@ impl<'tcx> intravisit::Visitor<'tcx> for CallgraphVisitor<'tcx> {
         // println!("Entering expr: {:#?}, constraint_depth{}", expr.kind, self.constraint_depth);
         // 检查表达式类型并更新约束层数
         match expr.kind {
-            rustc_hir::ExprKind::If(ref cond, ref then , ref else_ex) => {
+            rustc_hir::ExprKind::If(cond, then, else_ex) => {
                 self.enter_if = true;
                 intravisit::walk_expr(self, cond); // 处理条件表达式
                 self.enter_if = false;
-                match self.tcx.sess.source_map().span_to_snippet(expr.span) {
-                    Ok(snippet) => {
-                        if snippet.contains("debug") {  //应对debug_assert
-                        }
-                        else{
-                            self.constraint_depth += 1;
-                        }
-                    }
-                    _=>{
-                        self.constraint_depth += 1;
-                    }
-                }
-                intravisit::walk_expr(self, then); // 处理条件表达式
+                // `debug_assert!` only checks in debug builds, it does not constrain the calls
+                let condition = if Self::is_debug_assertion(expr.span) {
+                    None
+                } else {
+                    self.constraint_depth += 1;
+                    Some(self.formula(cond))
+                };
+                // `if let Err(e) = ..`
+                let then_path = match cond.kind {
+                    rustc_hir::ExprKind::Let(let_expr) => self.pattern_path(let_expr.pat),
+                    _ => graph::ControlPath::Normal,
+                };
+                self.guarded(then.span, condition.clone(), |this| {
+                    this.walk_branch(then, then_path, |this| intravisit::walk_expr(this, then))
+                });
                 // println!("into if expr:{:#?}, constraint:{}", expr, self.constraint_depth);
-                else_ex.as_ref().map(|expr| intravisit::walk_expr(self, expr));
-                
+                if let Some(else_ex) = else_ex {
+                    // the `else { break }` of a `while` loop has the span of the whole loop
+                    let condition = condition.filter(|_| else_ex.span != expr.span).map(graph::Condition::not);
+                    self.guarded(else_ex.span, condition, |this| {
+                        this.walk_branch(else_ex, graph::ControlPath::Normal, |this| intravisit::walk_expr(this, else_ex))
+                    });
+                }
             },
-            rustc_hir::ExprKind::Binary(op, ref lhs, ref rhs) => {
+            rustc_hir::ExprKind::Binary(op, lhs, rhs) => {
                 // 处理逻辑运算符
                 // println!("into binary, depth {:?}", self.constraint_depth);
                 flag = false;
@@ -513,7 +1126,7 @@ impl<'tcx> intravisit::Visitor<'tcx> for CallgraphVisitor<'tcx> {
                             self.visit_expr(lhs);
                             self.constraint_depth += 1; // 每个 and 增加一个约束深度
                             //println!("Entering And rhs, constraint_depth is {}, code: {:?}", self.constraint_depth, self.tcx.sess.source_map().span_to_snippet(rhs.span));
-                            self.visit_expr(rhs);
+                            self.visit_short_circuit(op.node, lhs, rhs);
                             // println!("And expr:{:#?}, constraint:{}", expr, self.constraint_depth);
                         },
                         rustc_hir::BinOpKind::Or => {
@@ -524,7 +1137,7 @@ impl<'tcx> intravisit::Visitor<'tcx> for CallgraphVisitor<'tcx> {
                             let left_constraint_depth = self.constraint_depth;
 
                             self.constraint_depth = left_depth; // 恢复之前的深度
-                            self.visit_expr(rhs);
+                            self.visit_short_circuit(op.node, lhs, rhs);
                             let right_constraint_depth = self.constraint_depth;
                             
                             // 选择最小的约束深度
@@ -540,12 +1153,12 @@ impl<'tcx> intravisit::Visitor<'tcx> for CallgraphVisitor<'tcx> {
                 }
                 else{
                     self.visit_expr(lhs);
-                    self.visit_expr(rhs);
+                    self.visit_short_circuit(op.node, lhs, rhs);
                 }
             },
-            rustc_hir::ExprKind::Match(ref match_expr, _, match_source) => {
+            rustc_hir::ExprKind::Match(match_expr, arms, match_source) => {
                 let not_for_loop_match = match match_expr.kind {
-                    rustc_hir::ExprKind::Call(ref callee, _) => {
+                    rustc_hir::ExprKind::Call(callee, _) => {
                         //println!("into Match call");
                         // 检查 callee 是否是 `next()` 方法，这通常是 `for` 循环的一部分
                         if let rustc_hir::ExprKind::Path(rustc_hir::QPath::LangItem(
@@ -580,22 +1193,85 @@ impl<'tcx> intravisit::Visitor<'tcx> for CallgraphVisitor<'tcx> {
                 }
                 
                 // println!("Match expr:{:#?}, constraint:{}", expr, self.constraint_depth);
-                intravisit::walk_expr(self, expr); // 确保遍历所有表达式
+                if is_real_match {
+                    self.visit_expr(match_expr);
+                    self.walk_match_arms(match_expr, arms);
+                } else if let (rustc_hir::MatchSource::TryDesugar(_), Some((continue_arm, break_arms))) = (match_source, arms.split_last()) {
+                    // `?`: the `Break` arm returns the residual
+                    self.visit_expr(match_expr);
+                    for arm in break_arms {
+                        self.on_path(Some(arm.body.span), graph::ControlPath::Error, |this| this.visit_arm(arm));
+                    }
+                    self.visit_arm(continue_arm);
+                } else {
+                    intravisit::walk_expr(self, expr); // 确保遍历所有表达式
+                }
                 // println!("Match expr:{:#?}, constraint:{}", expr, self.constraint_depth);
             },
+            rustc_hir::ExprKind::Block(block, _) => {
+                // the `unsafe` blocks of the standard library macros are not ours to audit
+                if let (Some(caller), rustc_hir::BlockCheckMode::UnsafeBlock(rustc_hir::UnsafeSource::UserProvided)) = (self.cur_fn, block.rules) {
+                    if !rustc_middle::lint::in_external_macro(self.tcx.sess, block.span) {
+                        let blocks = self.unsafe_blocks.entry(caller).or_default();
+                        if !blocks.contains(&block.span) {
+                            blocks.push(block.span);
+                        }
+                    }
+                }
+                intravisit::walk_expr(self, expr);
+            },
+            rustc_hir::ExprKind::Index(..) => {
+                if let Some(caller) = self.cur_fn {
+                    self.record_panic_site(caller, graph::PanicKind::Index, expr.span);
+                }
+                intravisit::walk_expr(self, expr);
+            },
+            rustc_hir::ExprKind::Loop(body, _, source, _) => {
+                self.walk_loop(expr, body, source);
+            },
             rustc_hir::ExprKind::Call(
                 rustc_hir::Expr {
                     kind: rustc_hir::ExprKind::Path(ref qpath),
                     ..
                 },
-                _,
+                args,
             ) => {
                 // println!("call path {:?}", qpath);
+                self.record_passed_closures(expr, args);
                 self.process_call(hir_id, qpath, expr);
             },
-            rustc_hir::ExprKind::MethodCall(ref segment, ref receiver, ref args, span) => {
+            rustc_hir::ExprKind::Call(callee, args) => {
+                self.record_passed_closures(expr, args);
+                self.process_indirect_call(hir_id, callee, expr);
+                intravisit::walk_expr(self, expr);
+            },
+            rustc_hir::ExprKind::MethodCall(ref segment, _, args, _) => {
+                self.record_passed_closures(expr, args);
                 self.process_method_call(hir_id, segment, expr);
             },
+            rustc_hir::ExprKind::Closure(closure) => {
+                // a closure is a node of its own, owned by the function defining it
+                let def_id = closure.def_id.to_def_id();
+                self.functions.insert((def_id, expr.span));
+                if let Some(parent) = self.cur_fn {
+                    self.closure_parents.insert(def_id, parent);
+                }
+
+                // the body does not run where the closure is defined
+                let prev_enter_if = self.enter_if;
+                self.enter_if = false;
+                self.constraint_depth = 0;
+                push_walk_pop!(self, def_id, intravisit::walk_expr(self, expr));
+                self.enter_if = prev_enter_if;
+            },
+            rustc_hir::ExprKind::Assign(lhs, rhs, _) => {
+                if let rustc_hir::ExprKind::Path(rustc_hir::QPath::Resolved(_, path)) = lhs.kind {
+                    if let Res::Local(local) = path.res {
+                        self.add_local_flow(local, rhs);
+                    }
+                }
+                intravisit::walk_expr(self, expr);
+            },
             _ => {
                 //println!("Processing other expression: {:?}", expr);
                 intravisit::walk_expr(self, expr); // 确保遍历所有表达式
@@ -607,6 +1283,44 @@ impl<'tcx> intravisit::Visitor<'tcx> for CallgraphVisitor<'tcx> {
         }
     }
 
+    fn visit_local(&mut self, local: &'tcx rustc_hir::LetStmt) {
+        if let (rustc_hir::PatKind::Binding(_, binding, _, None), Some(init)) = (local.pat.kind, local.init) {
+            self.add_local_flow(binding, init);
+        }
+        match (local.els, local.init) {
+            // the `else` block of a `let-else` runs when the pattern does not match
+            (Some(els), Some(init)) => {
+                self.visit_expr(init);
+                self.visit_pat(local.pat);
+                if let Some(ty) = local.ty {
+                    self.visit_ty(ty);
+                }
+                let condition = self.matches(local.pat, init, local.span).not();
+                let path = self.block_diverges_to(els);
+                self.guarded(els.span, Some(condition), |this| {
+                    this.on_path(Some(els.span), path, |this| this.visit_block(els))
+                });
+            }
+            _ => intravisit::walk_local(self, local),
+        }
+    }
+
+    fn visit_block(&mut self, block: &'tcx rustc_hir::Block) {
+        let depth = self.conditions.len();
+        for stmt in block.stmts {
+            self.visit_stmt(stmt);
+            // the rest of the block runs when the pattern of a `let-else` matched
+            if let rustc_hir::StmtKind::Let(rustc_hir::LetStmt { els: Some(_), init: Some(init), pat, span, .. }) = stmt.kind {
+                let condition = self.matches(pat, init, *span);
+                self.push_condition(block.span.with_lo(stmt.span.hi()), condition);
+            }
+        }
+        if let Some(expr) = block.expr {
+            self.visit_expr(expr);
+        }
+        self.conditions.truncate(depth);
+    }
+
     //解析函数定义，存储函数信息
     fn visit_item(&mut self, item: &'tcx rustc_hir::Item) {
         skip_generated_code!(item.span);
@@ -704,5 +1418,11 @@ impl<'tcx> intravisit::Visitor<'tcx> for CallgraphVisitor<'tcx> {
     }
 }
 
-
-
+// The type behind a reference, raw pointer or smart pointer like `Box` and `Rc`.
+fn pointee(ty: ty::Ty<'_>) -> Option<ty::Ty<'_>> {
+    match ty.kind() {
+        ty::Ref(_, inner, _) | ty::RawPtr(inner, _) => Some(*inner),
+        ty::Adt(_, args) => args.types().next(),
+        _ => None,
+    }
+}